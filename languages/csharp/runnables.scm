@@ -0,0 +1,40 @@
+; Test methods from xUnit, NUnit and MSTest in top-level classes. The
+; namespace, class and method are captured so the test task can filter on the
+; exact fully qualified name. Queries can't join the names of enclosing
+; declarations, so tests in nested classes or in namespaces nested inside
+; another namespace block aren't matched, and neither are tests in the global
+; namespace, which can't be told apart from a file-scoped namespace's classes.
+
+; namespace N { class C { [Fact] void M() {} } }
+(
+  (namespace_declaration
+    name: (_) @namespace
+    body: (declaration_list
+      (class_declaration
+        name: (identifier) @class_name
+        body: (declaration_list
+          (method_declaration
+            (attribute_list
+              (attribute
+                name: (identifier) @_attribute))
+            name: (identifier) @run @method_name)))))
+  (#any-of? @_attribute "Fact" "Theory" "Test" "TestCase" "TestCaseSource" "TestMethod" "DataTestMethod")
+  (#set! tag csharp-test)
+)
+
+; namespace N; class C { [Fact] void M() {} }
+(
+  (compilation_unit
+    (file_scoped_namespace_declaration
+      name: (_) @namespace)
+    (class_declaration
+      name: (identifier) @class_name
+      body: (declaration_list
+        (method_declaration
+          (attribute_list
+            (attribute
+              name: (identifier) @_attribute))
+          name: (identifier) @run @method_name))))
+  (#any-of? @_attribute "Fact" "Theory" "Test" "TestCase" "TestCaseSource" "TestMethod" "DataTestMethod")
+  (#set! tag csharp-test)
+)
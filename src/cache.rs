@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use crate::language_servers::{
+    nuget::{PACKAGES_DIR, SERVICE_INDEX_CACHE},
+    util::{self, RELEASE_CACHE_DIR, TEMP_DIR},
+    CsharpLs, Omnisharp, Roslyn,
+};
+use crate::logging::LOG_FILE;
+
+pub const LANGUAGE_SERVER_IDS: [&str; 3] = [
+    Omnisharp::LANGUAGE_SERVER_ID,
+    Roslyn::LANGUAGE_SERVER_ID,
+    CsharpLs::LANGUAGE_SERVER_ID,
+];
+
+/// The version and temporary directories the extension created in its working
+/// directory, including `nuget/<package>/<version>` installs, `tmp/`, and the
+/// cached GitHub releases and NuGet service index, sorted.
+pub fn owned_entries() -> Vec<String> {
+    let mut entries: Vec<String> = dir_names(".")
+        .into_iter()
+        .filter(|name| {
+            name == TEMP_DIR
+                || name == RELEASE_CACHE_DIR
+                || name == SERVICE_INDEX_CACHE
+                || LANGUAGE_SERVER_IDS.iter().any(|id| name.starts_with(id))
+        })
+        .collect();
+    for package in dir_names(PACKAGES_DIR) {
+        let package_dir = format!("{PACKAGES_DIR}/{package}");
+        for version in dir_names(&package_dir) {
+            entries.push(format!("{package_dir}/{version}"));
+        }
+    }
+    entries.sort();
+    entries
+}
+
+fn dir_names(path: &str) -> Vec<String> {
+    fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// What an entry of `owned_entries` belongs to in a disk usage breakdown:
+/// the language server for top-level installs, `nuget/<package>` for
+/// packages, or the entry itself.
+fn component(entry: &str) -> String {
+    if let Some(id) = LANGUAGE_SERVER_IDS.iter().find(|id| entry.starts_with(*id)) {
+        return id.to_string();
+    }
+    match entry.rsplit_once('/') {
+        Some((package_dir, _)) if entry.starts_with(PACKAGES_DIR) => package_dir.to_string(),
+        _ => entry.to_string(),
+    }
+}
+
+/// Bytes used in the working directory, by component, including the logs.
+pub fn disk_usage() -> BTreeMap<String, u64> {
+    let mut usage = BTreeMap::new();
+    for entry in owned_entries() {
+        *usage.entry(component(&entry)).or_default() += dir_size(Path::new(&entry));
+    }
+    let logs: u64 = dir_names(".")
+        .iter()
+        .filter(|name| name.starts_with(LOG_FILE))
+        .filter_map(|name| fs::metadata(name).ok())
+        .map(|stat| stat.len())
+        .sum();
+    if logs > 0 {
+        usage.insert("logs".to_string(), logs);
+    }
+    usage
+}
+
+fn dir_size(path: &Path) -> u64 {
+    let Ok(stat) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !stat.is_dir() {
+        return stat.len();
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .map(|entry| dir_size(&entry.path()))
+        .sum()
+}
+
+/// The language server an entry of `owned_entries` was installed for, keyed
+/// like `preserve_files`. NuGet package ids start with their server's id.
+fn language_server_for(entry: &str) -> Option<&'static str> {
+    let name = match entry.strip_prefix(PACKAGES_DIR) {
+        Some(package) => package.trim_start_matches('/'),
+        None => entry,
+    };
+    LANGUAGE_SERVER_IDS
+        .into_iter()
+        .find(|id| name.starts_with(id))
+}
+
+/// Whether `entry` is one of the `keep` directories. NuGet version
+/// directories are lowercase, so the comparison ignores ASCII case.
+fn is_kept(entry: &str, keep: &[String]) -> bool {
+    keep.iter()
+        .any(|dir| dir.trim_end_matches('/').eq_ignore_ascii_case(entry))
+}
+
+/// Removes the least recently modified version directories until the total
+/// disk usage is at most `cap_bytes`, and returns what was removed with its
+/// size.
+///
+/// Never removes the `keep` directories, `tmp/` or the cached metadata, and
+/// leaves each server's `preserve_files` in place. Nothing is removed while
+/// an install holds a lock that isn't stale, since its directory may be
+/// incomplete.
+pub fn collect_garbage(
+    cap_bytes: u64,
+    keep: &[String],
+    preserve_files: &BTreeMap<String, Vec<String>>,
+) -> Vec<(String, u64)> {
+    if util::install_in_progress() {
+        info!("skipping cache cleanup while an install is in progress");
+        return Vec::new();
+    }
+
+    let mut total: u64 = disk_usage().values().sum();
+    if total <= cap_bytes {
+        return Vec::new();
+    }
+
+    let mut candidates: Vec<(SystemTime, String)> = owned_entries()
+        .into_iter()
+        .filter(|entry| {
+            ![TEMP_DIR, RELEASE_CACHE_DIR, SERVICE_INDEX_CACHE].contains(&entry.as_str())
+                && !is_kept(entry, keep)
+        })
+        .map(|entry| {
+            let modified = fs::metadata(&entry)
+                .and_then(|stat| stat.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            (modified, entry)
+        })
+        .collect();
+    candidates.sort();
+
+    let mut removed = Vec::new();
+    for (_, entry) in candidates {
+        if total <= cap_bytes {
+            break;
+        }
+        let preserve = language_server_for(&entry)
+            .and_then(|id| preserve_files.get(id))
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let before = dir_size(Path::new(&entry));
+        util::remove_dir_preserving(Path::new(&entry), Path::new(""), preserve);
+        let size = before.saturating_sub(dir_size(Path::new(&entry)));
+        if size == 0 {
+            warn!("failed to remove '{entry}'");
+            continue;
+        }
+        info!(
+            "removed '{entry}' ({}) to stay under the cache size limit",
+            format_size(size)
+        );
+        total = total.saturating_sub(size);
+        removed.push((entry, size));
+    }
+    removed
+}
+
+/// E.g. `12.3 MB`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_only_the_listed_directories() {
+        let keep = [
+            "omnisharp-1.39.12".to_string(),
+            "nuget/roslyn-language-server.linux-x64/5.0.0-1.25277.114/".to_string(),
+        ];
+        assert!(is_kept("omnisharp-1.39.12", &keep));
+        assert!(is_kept(
+            "nuget/roslyn-language-server.linux-x64/5.0.0-1.25277.114",
+            &keep
+        ));
+        // The same version of another server or build isn't kept.
+        assert!(!is_kept("omnisharp-framework-1.39.12", &keep));
+        assert!(!is_kept("nuget/csharp-ls/1.39.12", &keep));
+        assert!(!is_kept("omnisharp-1.39.1", &keep));
+    }
+
+    #[test]
+    fn finds_the_language_server_of_an_entry() {
+        assert_eq!(
+            language_server_for("omnisharp-framework-1.39.12"),
+            Some("omnisharp")
+        );
+        assert_eq!(
+            language_server_for("nuget/roslyn-language-server.osx-arm64/5.0.0"),
+            Some("roslyn")
+        );
+        assert_eq!(
+            language_server_for("nuget/csharp-ls/0.20.0"),
+            Some("csharp-ls")
+        );
+        assert_eq!(language_server_for("releases"), None);
+    }
+}
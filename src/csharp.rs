@@ -1,66 +1,322 @@
+#[macro_use]
+mod logging;
+
+mod cache;
+mod dotnet;
+mod error;
+mod http;
+mod installed;
 mod language_servers;
+mod project;
+mod settings;
+mod slash_commands;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
 
-use zed_extension_api::{self as zed, Result};
+use zed_extension_api::{self as zed, settings::LspSettings, Result};
 
-use crate::language_servers::{CsharpLs, Omnisharp, Roslyn};
+use crate::dotnet::DotnetLocator;
+use crate::installed::InstalledVersions;
+use crate::language_servers::{nuget::NuGetClient, CsharpLs, Omnisharp, Roslyn};
+use crate::project::GodotProject;
+use crate::settings::CsharpSettings;
 
 struct CsharpExtension {
     omnisharp: Option<Omnisharp>,
     roslyn: Option<Roslyn>,
     csharp_ls: Option<CsharpLs>,
+    checked_worktrees: HashSet<u64>,
+    dotnet: DotnetLocator,
+    shell_envs: HashMap<u64, zed::EnvVars>,
+    nuget: Mutex<NuGetClient>,
+    installed: InstalledVersions,
+    /// Set by `/csharp-reinstall`, which only gets `&self`, so the servers'
+    /// cached state is dropped on the next start.
+    reinstalled: AtomicBool,
 }
 
-impl CsharpExtension {}
+impl CsharpExtension {
+    /// The shared NuGet client for slash commands, set up with the worktree's
+    /// extra headers.
+    fn nuget(&self, worktree: Option<&zed::Worktree>) -> Result<MutexGuard<'_, NuGetClient>> {
+        let mut nuget = self
+            .nuget
+            .lock()
+            .map_err(|_| "NuGet client is unavailable".to_string())?;
+        nuget.apply_settings(
+            &worktree
+                .map(CsharpSettings::for_worktree)
+                .unwrap_or_default(),
+        );
+        Ok(nuget)
+    }
 
-impl zed::Extension for CsharpExtension {
-    fn new() -> Self {
-        Self {
-            omnisharp: None,
-            roslyn: None,
-            csharp_ls: None,
+    /// Layers the environment for a server command, later entries winning: the
+    /// extension's .NET defaults, the worktree's shell environment, the
+    /// variables computed by the extension, then the user's `binary.env`.
+    fn command_env(
+        &mut self,
+        language_server_id: &str,
+        worktree: &zed::Worktree,
+        computed_env: zed::EnvVars,
+    ) -> zed::EnvVars {
+        let shell_env = self
+            .shell_envs
+            .entry(worktree.id())
+            .or_insert_with(|| worktree.shell_env())
+            .clone();
+        let user_env = LspSettings::for_worktree(language_server_id, worktree)
+            .ok()
+            .and_then(|lsp_settings| lsp_settings.binary)
+            .and_then(|binary| binary.env)
+            .unwrap_or_default();
+
+        let mut env: Vec<(String, String)> = Vec::new();
+        for (key, value) in dotnet::default_env()
+            .into_iter()
+            .chain(shell_env)
+            .chain(computed_env)
+            .chain(user_env)
+        {
+            match env.iter_mut().find(|(existing, _)| *existing == key) {
+                Some(entry) => entry.1 = value,
+                None => env.push((key, value)),
+            }
         }
+        env
     }
 
-    fn language_server_command(
+    fn server_command(
         &mut self,
         language_server_id: &zed::LanguageServerId,
         worktree: &zed::Worktree,
     ) -> Result<zed::Command> {
-        match language_server_id.as_ref() {
+        let settings = CsharpSettings::for_worktree(worktree);
+        logging::set_level(settings.log_level);
+        if !settings.enabled {
+            return Err(format!(
+                "C# language servers are disabled for this worktree ({}); \
+                 remove `lsp.csharp.settings.enabled: false` to enable them",
+                worktree.root_path()
+            ));
+        }
+
+        if std::mem::take(self.reinstalled.get_mut()) {
+            self.omnisharp = None;
+            self.roslyn = None;
+            self.csharp_ls = None;
+        }
+
+        let godot = project::find_godot_project(worktree);
+        if self.checked_worktrees.insert(worktree.id()) {
+            for problem in CsharpSettings::load(worktree).1 {
+                warn!("{problem}");
+            }
+            dotnet::check_workloads(worktree, &mut self.dotnet);
+            if let Some(godot) = &godot {
+                info!(
+                    "detected Godot {}",
+                    godot.version.as_deref().unwrap_or("(unknown version)")
+                );
+            }
+        }
+
+        let mut command = match language_server_id.as_ref() {
             Omnisharp::LANGUAGE_SERVER_ID => {
                 let omnisharp = self.omnisharp.get_or_insert_with(Omnisharp::new);
-                let omnisharp_binary =
-                    omnisharp.language_server_binary(language_server_id, worktree)?;
-                Ok(zed::Command {
+                let omnisharp_binary = omnisharp.language_server_binary(
+                    language_server_id,
+                    worktree,
+                    &mut self.dotnet,
+                )?;
+                zed::Command {
                     command: omnisharp_binary.path,
-                    args: omnisharp_binary.args.unwrap_or_else(|| vec!["-lsp".into()]),
+                    args: omnisharp_binary.args,
                     env: Default::default(),
-                })
+                }
             }
             Roslyn::LANGUAGE_SERVER_ID => {
                 // Add Roslyn Server
                 let roslyn = self.roslyn.get_or_insert_with(Roslyn::new);
-                roslyn.language_server_cmd(language_server_id, worktree)
+                roslyn.language_server_cmd(
+                    language_server_id,
+                    worktree,
+                    &mut self.dotnet,
+                    &self.installed,
+                )?
             }
             CsharpLs::LANGUAGE_SERVER_ID => {
                 let csharp_ls = self.csharp_ls.get_or_insert_with(CsharpLs::new);
-                csharp_ls.language_server_cmd(language_server_id, worktree)
+                csharp_ls.language_server_cmd(language_server_id, worktree, &mut self.dotnet)?
+            }
+            language_server_id => {
+                return Err(format!("unknown language server: {language_server_id}"))
+            }
+        };
+
+        // Apphosts such as the Roslyn server locate the runtime through
+        // DOTNET_ROOT, which a symlinked `dotnet` on PATH doesn't provide.
+        if let Some(root) = self.dotnet.root(worktree) {
+            command.env.push(("DOTNET_ROOT".to_string(), root));
+        }
+        if godot.is_some() {
+            command.env.extend(GodotProject::env(worktree));
+        }
+        command.env = self.command_env(language_server_id.as_ref(), worktree, command.env);
+        Ok(command)
+    }
+
+    fn record_installed_version(&mut self, language_server_id: &str, worktree: &zed::Worktree) {
+        let version = match language_server_id {
+            Omnisharp::LANGUAGE_SERVER_ID => self
+                .omnisharp
+                .as_ref()
+                .and_then(Omnisharp::installed_version),
+            Roslyn::LANGUAGE_SERVER_ID => self
+                .roslyn
+                .as_ref()
+                .and_then(Roslyn::binary_info)
+                .map(|binary| binary.version.as_str()),
+            CsharpLs::LANGUAGE_SERVER_ID => self
+                .csharp_ls
+                .as_ref()
+                .and_then(CsharpLs::installed_version),
+            _ => None,
+        };
+        let Some(version) = version else {
+            return;
+        };
+        // A new version was installed, so older ones may now exceed the cap.
+        if self.installed.record(language_server_id, version) {
+            let settings = CsharpSettings::for_worktree(worktree);
+            if settings.manage_installs {
+                cache::collect_garbage(
+                    settings.cache_size_mb.saturating_mul(1024 * 1024),
+                    &self.cache_keep_list(&settings),
+                    &settings.preserve_files,
+                );
             }
-            language_server_id => Err(format!("unknown language server: {language_server_id}")),
         }
     }
 
+    /// The install directories cache cleanup must keep: the running ones,
+    /// and each server's own pinned and recorded versions.
+    fn cache_keep_list(&self, settings: &CsharpSettings) -> Vec<String> {
+        let mut keep: Vec<String> = [
+            self.omnisharp.as_ref().and_then(Omnisharp::install_dir),
+            self.roslyn.as_ref().and_then(Roslyn::install_dir),
+            self.csharp_ls.as_ref().and_then(CsharpLs::install_dir),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let versions = settings
+            .server_versions
+            .iter()
+            .map(|(id, version)| (id.as_str(), version.as_str()))
+            .chain(
+                self.installed
+                    .iter()
+                    .map(|(id, installed)| (id, installed.version.as_str())),
+            );
+        for (id, version) in versions {
+            match id {
+                Omnisharp::LANGUAGE_SERVER_ID => keep.extend(Omnisharp::version_dirs(version)),
+                Roslyn::LANGUAGE_SERVER_ID => keep.push(Roslyn::version_dir(version)),
+                CsharpLs::LANGUAGE_SERVER_ID => keep.push(CsharpLs::version_dir(version)),
+                _ => {}
+            }
+        }
+        keep
+    }
+}
+
+impl zed::Extension for CsharpExtension {
+    fn new() -> Self {
+        Self {
+            omnisharp: None,
+            roslyn: None,
+            csharp_ls: None,
+            checked_worktrees: HashSet::new(),
+            dotnet: DotnetLocator::default(),
+            shell_envs: HashMap::new(),
+            nuget: Mutex::new(NuGetClient::new()),
+            installed: InstalledVersions::load(),
+            reinstalled: AtomicBool::new(false),
+        }
+    }
+
+    fn language_server_command(
+        &mut self,
+        language_server_id: &zed::LanguageServerId,
+        worktree: &zed::Worktree,
+    ) -> Result<zed::Command> {
+        let result = self.server_command(language_server_id, worktree);
+        if result.is_ok() {
+            self.record_installed_version(language_server_id.as_ref(), worktree);
+        }
+        // Name the installed version in errors so bug reports carry it.
+        let installed = self.installed.describe(language_server_id.as_ref());
+        result.map_err(|e| {
+            let e = match installed {
+                Some(installed) => format!("{e} ({installed})"),
+                None => e,
+            };
+            error!("failed to start {}: {e}", language_server_id.as_ref());
+            format!("{e}; see {} for details", logging::log_path())
+        })
+    }
+
     fn language_server_workspace_configuration(
         &mut self,
         language_server_id: &zed::LanguageServerId,
         worktree: &zed::Worktree,
     ) -> Result<Option<zed::serde_json::Value>> {
         match language_server_id.as_ref() {
-            Roslyn::LANGUAGE_SERVER_ID => Roslyn::configuration_options(worktree),
+            Omnisharp::LANGUAGE_SERVER_ID => Omnisharp::configuration_options(worktree),
+            Roslyn::LANGUAGE_SERVER_ID => {
+                Roslyn::configuration_options(worktree).map_err(String::from)
+            }
             CsharpLs::LANGUAGE_SERVER_ID => CsharpLs::configuration_options(worktree),
             _ => Ok(None),
         }
     }
+
+    fn run_slash_command(
+        &self,
+        command: zed::SlashCommand,
+        args: Vec<String>,
+        worktree: Option<&zed::Worktree>,
+    ) -> Result<zed::SlashCommandOutput> {
+        match command.name.as_str() {
+            "csharp-doctor" => slash_commands::doctor(
+                worktree,
+                self.roslyn.as_ref().and_then(Roslyn::binary_info),
+                &self.installed,
+            ),
+            "csharp-reinstall" => {
+                let output = slash_commands::reinstall()?;
+                self.reinstalled.store(true, Ordering::Relaxed);
+                Ok(output)
+            }
+            "csharp-clean-caches" => {
+                let settings = worktree
+                    .map(CsharpSettings::for_worktree)
+                    .unwrap_or_default();
+                slash_commands::clean_caches(
+                    &self.cache_keep_list(&settings),
+                    &settings.preserve_files,
+                )
+            }
+            "csharp-new-type" => slash_commands::new_type(worktree, args),
+            "csharp-add-package" => slash_commands::add_package(&mut *self.nuget(worktree)?, args),
+            "csharp-versions" => slash_commands::versions(&mut *self.nuget(worktree)?, args),
+            "nuget" => slash_commands::nuget(&mut *self.nuget(worktree)?, args),
+            command => Err(format!("unknown slash command: \"{command}\"")),
+        }
+    }
 }
 
 zed::register_extension!(CsharpExtension);
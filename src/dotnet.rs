@@ -0,0 +1,701 @@
+use std::collections::HashMap;
+
+use zed_extension_api::{self as zed, process::Command, Result};
+
+use crate::settings::CsharpSettings;
+
+/// Resolves the `dotnet` installation and remembers the result per worktree.
+///
+/// All code that needs to run `dotnet` should go through this so that the
+/// probing order and the `dotnet_path` override apply everywhere.
+#[derive(Default)]
+pub struct DotnetLocator {
+    resolved: HashMap<u64, ResolvedDotnet>,
+}
+
+struct ResolvedDotnet {
+    configured_path: Option<String>,
+    path: String,
+    /// The install root, when it is known without running `dotnet`.
+    root: Option<String>,
+    install: Option<DotnetInstall>,
+}
+
+/// Where `locate` found `dotnet`.
+#[derive(Debug, PartialEq)]
+struct Located {
+    path: String,
+    /// `None` for an explicit path or one on PATH, which may be a symlink
+    /// into the install.
+    root: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct DotnetInstall {
+    pub executable: String,
+    pub root: Option<String>,
+    pub sdks: Vec<DotnetComponent>,
+    pub runtimes: Vec<DotnetComponent>,
+}
+
+/// One line of `dotnet --list-sdks` or `dotnet --list-runtimes`.
+#[derive(Clone)]
+pub struct DotnetComponent {
+    /// The runtime name, e.g. `Microsoft.NETCore.App`. Empty for SDKs.
+    pub name: String,
+    pub version: String,
+    pub path: String,
+}
+
+impl DotnetLocator {
+    /// Returns the `dotnet` executable, probing in order:
+    ///
+    /// 1. `dotnet_path` from the extension settings
+    /// 2. `dotnet` on the worktree's shell PATH
+    /// 3. `dotnet` inside `DOTNET_ROOT` from the worktree's shell environment
+    /// 4. the default install locations of the installers, Homebrew and
+    ///    `dotnet-install`, see `well_known_roots`
+    ///
+    /// A cached path is reused until `dotnet_path` changes. Paths outside the
+    /// extension's working directory can't be inspected from the sandbox, so
+    /// the default locations are probed by running `dotnet --list-sdks` from
+    /// each, and the cache can't notice a dotnet install being removed.
+    /// Failed lookups are not cached.
+    pub fn find(&mut self, worktree: &zed::Worktree) -> Option<String> {
+        self.resolve(worktree).map(|resolved| resolved.path.clone())
+    }
+
+    /// The install root to pass as `DOTNET_ROOT`. Only runs `dotnet` when
+    /// neither the shell environment nor the probing order tells where the
+    /// install is, e.g. for a symlinked `dotnet` on PATH, and then only once
+    /// per worktree.
+    pub fn root(&mut self, worktree: &zed::Worktree) -> Option<String> {
+        if let Some(root) = dotnet_root(worktree) {
+            return Some(root);
+        }
+        if let Some(root) = self.resolve(worktree)?.root.clone() {
+            return Some(root);
+        }
+        self.install(worktree).and_then(|install| install.root)
+    }
+
+    /// Returns the resolved installation along with its SDKs and runtimes.
+    /// This runs `dotnet`, so it is only done on demand.
+    pub fn install(&mut self, worktree: &zed::Worktree) -> Option<DotnetInstall> {
+        let resolved = self.resolve(worktree)?;
+        if resolved.install.is_none() {
+            resolved.install = Some(query_install(worktree, &resolved.path));
+        }
+        resolved.install.clone()
+    }
+
+    fn resolve(&mut self, worktree: &zed::Worktree) -> Option<&mut ResolvedDotnet> {
+        let configured_path = CsharpSettings::for_worktree(worktree).dotnet_path;
+
+        if self
+            .resolved
+            .get(&worktree.id())
+            .is_some_and(|resolved| resolved.configured_path == configured_path)
+        {
+            return self.resolved.get_mut(&worktree.id());
+        }
+
+        let platform = zed::current_platform().0;
+        let shell_env = worktree.shell_env();
+        let home_key = match platform {
+            zed::Os::Windows => "USERPROFILE",
+            _ => "HOME",
+        };
+        let home = shell_env
+            .iter()
+            .find(|(key, value)| key.eq_ignore_ascii_case(home_key) && !value.is_empty())
+            .map(|(_, value)| value.as_str());
+        let located = locate(
+            configured_path.clone(),
+            || worktree.which("dotnet"),
+            dotnet_root(worktree),
+            &well_known_roots(platform, home),
+            platform,
+            |root| {
+                command(worktree, &executable_in(root, platform))
+                    .arg("--list-sdks")
+                    .output()
+                    .is_ok_and(|output| output.status == Some(0))
+            },
+        )?;
+        debug!("using dotnet at '{}'", located.path);
+        self.resolved.insert(
+            worktree.id(),
+            ResolvedDotnet {
+                configured_path,
+                path: located.path,
+                root: located.root,
+                install: None,
+            },
+        );
+        self.resolved.get_mut(&worktree.id())
+    }
+}
+
+fn dotnet_root(worktree: &zed::Worktree) -> Option<String> {
+    worktree
+        .shell_env()
+        .into_iter()
+        .find_map(|(key, value)| (key == "DOTNET_ROOT" && !value.is_empty()).then_some(value))
+}
+
+fn executable_in(root: &str, platform: zed::Os) -> String {
+    let root = root.trim_end_matches(['/', '\\']);
+    match platform {
+        zed::Os::Windows => format!("{root}\\dotnet.exe"),
+        _ => format!("{root}/dotnet"),
+    }
+}
+
+/// The probing order of `DotnetLocator::find`, with the lookups that need
+/// the host passed in. `runs_from` tells whether `dotnet` runs from one of
+/// the `well_known_roots`; an explicit `dotnet_root` is trusted as is.
+fn locate(
+    configured_path: Option<String>,
+    on_path: impl FnOnce() -> Option<String>,
+    dotnet_root: Option<String>,
+    well_known_roots: &[String],
+    platform: zed::Os,
+    mut runs_from: impl FnMut(&str) -> bool,
+) -> Option<Located> {
+    if let Some(path) = configured_path.or_else(on_path) {
+        return Some(Located { path, root: None });
+    }
+    dotnet_root
+        .into_iter()
+        .chain(
+            well_known_roots
+                .iter()
+                .filter(|root| runs_from(root))
+                .take(1)
+                .cloned(),
+        )
+        .next()
+        .map(|root| Located {
+            path: executable_in(&root, platform),
+            root: Some(root),
+        })
+}
+
+/// Where the official installers, Homebrew, the Linux distribution packages
+/// and `dotnet-install` put .NET, most common first. `home` is the user's
+/// home directory.
+///
+/// `/etc/dotnet/install_location`, which names the location on Linux and
+/// macOS, is outside the sandbox and can't be read.
+fn well_known_roots(platform: zed::Os, home: Option<&str>) -> Vec<String> {
+    let mut roots: Vec<String> = match platform {
+        zed::Os::Mac => vec![
+            "/usr/local/share/dotnet".into(),
+            "/opt/homebrew/opt/dotnet/libexec".into(),
+            "/usr/local/opt/dotnet/libexec".into(),
+        ],
+        zed::Os::Linux => vec![
+            "/usr/share/dotnet".into(),
+            "/usr/lib/dotnet".into(),
+            "/usr/lib64/dotnet".into(),
+            "/home/linuxbrew/.linuxbrew/opt/dotnet/libexec".into(),
+        ],
+        zed::Os::Windows => vec!["C:\\Program Files\\dotnet".into()],
+    };
+    if let Some(home) = home.map(|home| home.trim_end_matches(['/', '\\'])) {
+        roots.push(match platform {
+            zed::Os::Windows => format!("{home}\\AppData\\Local\\Microsoft\\dotnet"),
+            _ => format!("{home}/.dotnet"),
+        });
+    }
+    roots
+}
+
+fn query_install(worktree: &zed::Worktree, executable: &str) -> DotnetInstall {
+    let list = |flag: &str| {
+        run_command(worktree, executable, &[flag])
+            .map(|output| parse_component_list(&output))
+            .unwrap_or_default()
+    };
+    let sdks = list("--list-sdks");
+    let runtimes = list("--list-runtimes");
+    let root = dotnet_root(worktree).or_else(|| root_from_components(&sdks, &runtimes));
+    DotnetInstall {
+        executable: executable.to_string(),
+        root,
+        sdks,
+        runtimes,
+    }
+}
+
+/// Derives the install root from the paths `dotnet` reports for its SDKs
+/// (`<root>/sdk`) or runtimes (`<root>/shared/<name>`).
+///
+/// `dotnet` reports the real location of its install, so this points at the
+/// actual SDK layout when the `dotnet` on PATH is a symlink (e.g. Homebrew),
+/// which the sandbox can't resolve itself.
+fn root_from_components(sdks: &[DotnetComponent], runtimes: &[DotnetComponent]) -> Option<String> {
+    let parent = |path: &str| {
+        path.trim_end_matches(['/', '\\'])
+            .rsplit_once(['/', '\\'])
+            .map(|(parent, _)| parent.to_string())
+    };
+    sdks.first().and_then(|sdk| parent(&sdk.path)).or_else(|| {
+        let shared = parent(&runtimes.first()?.path)?;
+        parent(&shared)
+    })
+}
+
+/// Parses lines like `8.0.100 [/usr/share/dotnet/sdk]` or
+/// `Microsoft.NETCore.App 8.0.0 [/usr/share/dotnet/shared/Microsoft.NETCore.App]`.
+fn parse_component_list(output: &str) -> Vec<DotnetComponent> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (head, path) = line.trim().split_once(" [")?;
+            let path = path.strip_suffix(']')?.to_string();
+            let (name, version) = match head.rsplit_once(' ') {
+                Some((name, version)) => (name.to_string(), version.to_string()),
+                None => (String::new(), head.to_string()),
+            };
+            Some(DotnetComponent {
+                name,
+                version,
+                path,
+            })
+        })
+        .collect()
+}
+
+/// Environment defaults for every process the extension starts. Both can be
+/// overridden from the shell environment or `binary.env`:
+///
+/// - `DOTNET_NOLOGO` keeps the .NET banner out of the server logs.
+/// - `DOTNET_CLI_UI_LANGUAGE` keeps runtime errors in English regardless of
+///   locale, so they read the same in every bug report.
+pub fn default_env() -> zed::EnvVars {
+    vec![
+        ("DOTNET_NOLOGO".to_string(), "1".to_string()),
+        ("DOTNET_CLI_UI_LANGUAGE".to_string(), "en-US".to_string()),
+    ]
+}
+
+/// A command that runs the `dotnet` at `executable`.
+///
+/// The extension may only start processes named `dotnet`, so instead of
+/// spawning `executable` by path its directory is put first on the PATH
+/// from the worktree's shell environment.
+pub fn command(worktree: &zed::Worktree, executable: &str) -> Command {
+    let separator = match zed::current_platform().0 {
+        zed::Os::Windows => ';',
+        _ => ':',
+    };
+    let mut env = worktree.shell_env();
+    if let Some(dir) = executable
+        .rsplit_once(['/', '\\'])
+        .map(|(dir, _)| dir)
+        .filter(|dir| !dir.is_empty())
+    {
+        prepend_to_path(&mut env, dir, separator);
+    }
+    Command::new("dotnet").envs(default_env()).envs(env)
+}
+
+/// Puts `dir` first on the `PATH` in `env`, whose key Windows spells in any
+/// case.
+fn prepend_to_path(env: &mut zed::EnvVars, dir: &str, separator: char) {
+    match env
+        .iter_mut()
+        .find(|(key, _)| key.eq_ignore_ascii_case("PATH"))
+    {
+        Some((_, path)) if !path.is_empty() => *path = format!("{dir}{separator}{path}"),
+        Some((_, path)) => *path = dir.to_string(),
+        None => env.push(("PATH".to_string(), dir.to_string())),
+    }
+}
+
+/// Warns about workloads listed in `required_workloads` that are not
+/// installed. Only runs when `check_workloads` is enabled and never fails the
+/// server start.
+pub fn check_workloads(worktree: &zed::Worktree, locator: &mut DotnetLocator) {
+    let settings = CsharpSettings::for_worktree(worktree);
+    if !settings.check_workloads || settings.required_workloads.is_empty() {
+        return;
+    }
+
+    match installed_workloads(worktree, locator) {
+        Ok(installed) => {
+            let missing: Vec<&str> = settings
+                .required_workloads
+                .iter()
+                .map(String::as_str)
+                .filter(|workload| !installed.iter().any(|i| i.eq_ignore_ascii_case(workload)))
+                .collect();
+            if !missing.is_empty() {
+                warn!(
+                    "missing .NET workloads: {}; install them with `dotnet workload install {}`",
+                    missing.join(", "),
+                    missing.join(" ")
+                );
+            }
+        }
+        Err(e) => warn!("failed to verify .NET workloads: {e}"),
+    }
+}
+
+fn installed_workloads(
+    worktree: &zed::Worktree,
+    locator: &mut DotnetLocator,
+) -> Result<Vec<String>> {
+    let output = run_dotnet(worktree, locator, &["workload", "list"])?;
+    Ok(parse_workload_list(&output))
+}
+
+/// Runs `dotnet` with the worktree's shell environment and returns its stdout.
+pub fn run_dotnet(
+    worktree: &zed::Worktree,
+    locator: &mut DotnetLocator,
+    args: &[&str],
+) -> Result<String> {
+    let dotnet = locator
+        .find(worktree)
+        .ok_or("dotnet was not found on PATH")?;
+    run_command(worktree, &dotnet, args)
+}
+
+fn run_command(worktree: &zed::Worktree, dotnet: &str, args: &[&str]) -> Result<String> {
+    let output = command(worktree, dotnet)
+        .args(args.iter().copied())
+        .output()?;
+    if output.status != Some(0) {
+        return Err(format!(
+            "`dotnet {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Extracts the workload ids from the table printed by `dotnet workload list`.
+///
+/// The ids are the first column of the rows between the dashed separator and
+/// the next blank line.
+fn parse_workload_list(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.trim_start().starts_with("---"))
+        .skip(1)
+        .take_while(|line| !line.trim().is_empty())
+        .filter_map(|line| line.split_whitespace().next())
+        .map(str::to_string)
+        .collect()
+}
+
+pub struct DirectoryBuildProps {
+    pub target_framework: Option<String>,
+    /// Every framework named by `TargetFramework`, `TargetFrameworks` or
+    /// `TargetFrameworkVersion`.
+    pub target_frameworks: Vec<String>,
+    /// `PackageReference`s that look like analyzer packages, e.g.
+    /// `StyleCop.Analyzers`.
+    pub analyzer_packages: Vec<String>,
+}
+
+/// Reads the `Directory.Build.props` at the worktree root, if any.
+///
+/// Extensions can only read files inside the worktree, so props files in
+/// parent directories of the worktree are not considered.
+pub fn find_directory_build_props(worktree: &zed::Worktree) -> Option<DirectoryBuildProps> {
+    let content = worktree.read_text_file("Directory.Build.props").ok()?;
+    let target_framework = xml_element_text(&content, "TargetFramework").map(str::to_string);
+    let target_frameworks = [
+        "TargetFramework",
+        "TargetFrameworks",
+        "TargetFrameworkVersion",
+    ]
+    .into_iter()
+    .filter_map(|tag| xml_element_text(&content, tag))
+    .flat_map(|value| value.split(';'))
+    .map(str::trim)
+    .filter(|tfm| !tfm.is_empty())
+    .map(str::to_string)
+    .collect();
+    let analyzer_packages = package_references(&content)
+        .filter(|package| package.to_ascii_lowercase().contains("analyzers"))
+        .map(str::to_string)
+        .collect();
+    Some(DirectoryBuildProps {
+        target_framework,
+        target_frameworks,
+        analyzer_packages,
+    })
+}
+
+impl DirectoryBuildProps {
+    /// The targets that are .NET Framework rather than .NET (Core).
+    pub fn net_framework_targets(&self) -> Vec<&str> {
+        self.target_frameworks
+            .iter()
+            .map(String::as_str)
+            .filter(|tfm| is_net_framework(tfm))
+            .collect()
+    }
+
+    /// Whether there are targets and all of them are .NET Framework.
+    pub fn targets_only_net_framework(&self) -> bool {
+        !self.target_frameworks.is_empty()
+            && self.net_framework_targets().len() == self.target_frameworks.len()
+    }
+}
+
+/// The `Include` of every `<PackageReference>` in an MSBuild file.
+fn package_references(content: &str) -> impl Iterator<Item = &str> {
+    content
+        .split("<PackageReference")
+        .skip(1)
+        .filter_map(|element| {
+            let element = &element[..element.find('>')?];
+            let include = &element[element.find("Include=\"")? + "Include=\"".len()..];
+            Some(include[..include.find('"')?].trim())
+        })
+}
+
+/// Whether `tfm` is a .NET Framework target: a short name like `net472` or a
+/// legacy `TargetFrameworkVersion` like `v4.7.2`. `net5.0` and later always
+/// contain a dot, so they don't match.
+pub fn is_net_framework(tfm: &str) -> bool {
+    if let Some(version) = tfm.strip_prefix('v') {
+        return version.starts_with(|c: char| c.is_ascii_digit());
+    }
+    tfm.strip_prefix("net").is_some_and(|version| {
+        version.starts_with(['1', '2', '3', '4']) && version.chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+/// Returns the trimmed text of the first `<tag>...</tag>` element in an
+/// MSBuild file. Attributes on the element are not supported.
+pub fn xml_element_text<'a>(content: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{tag}>");
+    let close = format!("</{tag}>");
+    let start = content.find(&open)? + open.len();
+    let end = start + content[start..].find(&close)?;
+    Some(content[start..end].trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probes_dotnet_in_order() {
+        let roots = well_known_roots(zed::Os::Linux, Some("/home/me"));
+        let unreachable = || -> Option<String> { panic!("looked up PATH") };
+        let never_runs = |root: &str| -> bool { panic!("probed {root}") };
+
+        let configured = locate(
+            Some("/opt/dotnet/dotnet".into()),
+            unreachable,
+            None,
+            &roots,
+            zed::Os::Linux,
+            never_runs,
+        );
+        assert_eq!(
+            configured,
+            Some(Located {
+                path: "/opt/dotnet/dotnet".into(),
+                root: None
+            })
+        );
+
+        let on_path = locate(
+            None,
+            || Some("/usr/bin/dotnet".into()),
+            Some("/opt/dotnet".into()),
+            &roots,
+            zed::Os::Linux,
+            never_runs,
+        );
+        assert_eq!(on_path.unwrap().path, "/usr/bin/dotnet");
+
+        let from_dotnet_root = locate(
+            None,
+            || None,
+            Some("/opt/dotnet/".into()),
+            &roots,
+            zed::Os::Linux,
+            never_runs,
+        );
+        assert_eq!(
+            from_dotnet_root,
+            Some(Located {
+                path: "/opt/dotnet/dotnet".into(),
+                root: Some("/opt/dotnet/".into())
+            })
+        );
+
+        let mut probed = Vec::new();
+        let well_known = locate(
+            None,
+            || None,
+            None,
+            &roots,
+            zed::Os::Linux,
+            |root| {
+                probed.push(root.to_string());
+                root == "/usr/lib64/dotnet"
+            },
+        );
+        assert_eq!(
+            well_known,
+            Some(Located {
+                path: "/usr/lib64/dotnet/dotnet".into(),
+                root: Some("/usr/lib64/dotnet".into())
+            })
+        );
+        assert_eq!(
+            probed,
+            ["/usr/share/dotnet", "/usr/lib/dotnet", "/usr/lib64/dotnet"]
+        );
+
+        assert_eq!(
+            locate(None, || None, None, &roots, zed::Os::Linux, |_| false),
+            None
+        );
+    }
+
+    #[test]
+    fn lists_the_default_install_locations() {
+        assert_eq!(
+            well_known_roots(zed::Os::Mac, Some("/Users/me/")),
+            [
+                "/usr/local/share/dotnet",
+                "/opt/homebrew/opt/dotnet/libexec",
+                "/usr/local/opt/dotnet/libexec",
+                "/Users/me/.dotnet",
+            ]
+        );
+        assert_eq!(
+            well_known_roots(zed::Os::Windows, Some("C:\\Users\\me")),
+            [
+                "C:\\Program Files\\dotnet",
+                "C:\\Users\\me\\AppData\\Local\\Microsoft\\dotnet",
+            ]
+        );
+        assert_eq!(well_known_roots(zed::Os::Linux, None).len(), 4);
+        assert_eq!(
+            executable_in("C:\\Program Files\\dotnet\\", zed::Os::Windows),
+            "C:\\Program Files\\dotnet\\dotnet.exe"
+        );
+    }
+
+    #[test]
+    fn prepends_the_executable_directory_to_path() {
+        let mut env = vec![("Path".to_string(), "C:\\Windows".to_string())];
+        prepend_to_path(&mut env, "C:\\Program Files\\dotnet", ';');
+        assert_eq!(
+            env,
+            [(
+                "Path".to_string(),
+                "C:\\Program Files\\dotnet;C:\\Windows".to_string()
+            )]
+        );
+
+        let mut env = Vec::new();
+        prepend_to_path(&mut env, "/usr/local/share/dotnet", ':');
+        assert_eq!(
+            env,
+            [("PATH".to_string(), "/usr/local/share/dotnet".to_string())]
+        );
+    }
+
+    #[test]
+    fn parses_installed_workloads() {
+        let output = "
+Workload version: 9.0.100-manifests.4a280210
+
+Installed Workload Id      Manifest Version      Installation Source
+--------------------------------------------------------------------
+maui-android               9.0.0/9.0.100         SDK 9.0.100
+wasm-tools                 9.0.0/9.0.100         SDK 9.0.100
+
+Use `dotnet workload search` to find additional workloads to install.
+";
+        assert_eq!(parse_workload_list(output), ["maui-android", "wasm-tools"]);
+    }
+
+    #[test]
+    fn parses_no_installed_workloads() {
+        let output = "
+Installed Workload Id      Manifest Version      Installation Source
+--------------------------------------------------------------------
+
+Use `dotnet workload search` to find additional workloads to install.
+";
+        assert!(parse_workload_list(output).is_empty());
+        assert!(parse_workload_list("").is_empty());
+    }
+
+    #[test]
+    fn parses_sdks_and_runtimes() {
+        let sdks = parse_component_list(
+            "8.0.404 [/usr/share/dotnet/sdk]\n9.0.100-rc.2.24474.11 [/usr/share/dotnet/sdk]\n",
+        );
+        assert_eq!(sdks.len(), 2);
+        assert_eq!(sdks[1].name, "");
+        assert_eq!(sdks[1].version, "9.0.100-rc.2.24474.11");
+        assert_eq!(sdks[1].path, "/usr/share/dotnet/sdk");
+
+        let runtimes = parse_component_list(
+            "Microsoft.AspNetCore.App 8.0.11 [C:\\Program Files\\dotnet\\shared\\Microsoft.AspNetCore.App]\r\n\
+             Microsoft.NETCore.App 8.0.11 [C:\\Program Files\\dotnet\\shared\\Microsoft.NETCore.App]\r\n",
+        );
+        assert_eq!(runtimes.len(), 2);
+        assert_eq!(runtimes[1].name, "Microsoft.NETCore.App");
+        assert_eq!(runtimes[1].version, "8.0.11");
+
+        assert!(parse_component_list("No .NET SDKs were found.").is_empty());
+    }
+
+    #[test]
+    fn recognizes_net_framework_targets() {
+        for tfm in ["net472", "net48", "net20", "v4.7.2"] {
+            assert!(is_net_framework(tfm), "{tfm}");
+        }
+        for tfm in [
+            "net8.0",
+            "net5.0-windows",
+            "netstandard2.0",
+            "netcoreapp3.1",
+            "vnext",
+        ] {
+            assert!(!is_net_framework(tfm), "{tfm}");
+        }
+    }
+
+    #[test]
+    fn finds_the_root_from_components() {
+        let component = |path: &str| DotnetComponent {
+            name: String::new(),
+            version: String::new(),
+            path: path.to_string(),
+        };
+        assert_eq!(
+            root_from_components(&[component("/usr/share/dotnet/sdk")], &[]).as_deref(),
+            Some("/usr/share/dotnet")
+        );
+        assert_eq!(
+            root_from_components(
+                &[],
+                &[component(
+                    "C:\\Program Files\\dotnet\\shared\\Microsoft.NETCore.App\\"
+                )]
+            )
+            .as_deref(),
+            Some("C:\\Program Files\\dotnet")
+        );
+        assert_eq!(root_from_components(&[], &[]), None);
+    }
+}
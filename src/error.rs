@@ -0,0 +1,92 @@
+use std::fmt;
+
+/// What went wrong, so callers can decide whether to retry, fall back to an
+/// install on disk, or point the user at a setting. Converts into the
+/// `String` errors Zed expects with `?`.
+#[derive(Debug)]
+pub enum Error {
+    /// A request failed or timed out.
+    Network(String),
+    /// The server asked us to slow down.
+    RateLimited(String),
+    /// A package, version, asset or resource doesn't exist.
+    NotFound(String),
+    /// Reading or writing the working directory failed.
+    Io(String),
+    /// A response or file didn't have the expected shape.
+    Corrupted(String),
+    /// The current platform isn't supported.
+    Unsupported(String),
+    /// A setting, named without the `lsp.csharp.settings.` prefix, prevents
+    /// the operation or is invalid.
+    Configuration {
+        setting: &'static str,
+        message: String,
+    },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Classifies an error reported by the host's HTTP client or downloader.
+    /// Only the message is available, so rate limiting is recognized by its
+    /// status code or wording.
+    pub fn network(context: impl fmt::Display, error: impl fmt::Display) -> Self {
+        let error = error.to_string();
+        let lower = error.to_ascii_lowercase();
+        let message = format!("{context}: {error}");
+        if lower.contains("429") || lower.contains("rate limit") {
+            Self::RateLimited(message)
+        } else {
+            Self::Network(message)
+        }
+    }
+
+    pub fn io(context: impl fmt::Display, error: impl fmt::Display) -> Self {
+        Self::Io(format!("{context}: {error}"))
+    }
+
+    pub fn corrupted(context: impl fmt::Display, error: impl fmt::Display) -> Self {
+        Self::Corrupted(format!("{context}: {error}"))
+    }
+
+    pub fn configuration(setting: &'static str, message: impl Into<String>) -> Self {
+        Self::Configuration {
+            setting,
+            message: message.into(),
+        }
+    }
+
+    /// Whether trying again may succeed. Feeds sometimes serve truncated or
+    /// incomplete documents, so `Corrupted` and `NotFound` are retried too.
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, Self::Unsupported(_) | Self::Configuration { .. })
+    }
+
+    /// Whether the network is unavailable or refusing requests, so that an
+    /// install already on disk should be used instead.
+    pub fn is_offline(&self) -> bool {
+        matches!(self, Self::Network(_) | Self::RateLimited(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(message)
+            | Self::NotFound(message)
+            | Self::Io(message)
+            | Self::Corrupted(message)
+            | Self::Unsupported(message) => f.write_str(message),
+            Self::RateLimited(message) => write!(f, "{message} (rate limited; try again later)"),
+            // Messages already name the setting.
+            Self::Configuration { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl From<Error> for String {
+    fn from(error: Error) -> Self {
+        error.to_string()
+    }
+}
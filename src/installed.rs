@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use zed_extension_api::serde_json;
+
+/// Kept next to the installs in the extension's working directory.
+const REGISTRY_FILE: &str = "installed.json";
+
+/// The versions the extension has installed, by language server id. Entries
+/// are added as servers are resolved and saved so they survive restarts.
+#[derive(Default, Serialize, Deserialize)]
+pub struct InstalledVersions {
+    components: BTreeMap<String, InstalledComponent>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InstalledComponent {
+    pub version: String,
+    /// Seconds since the Unix epoch.
+    pub installed_at: u64,
+}
+
+impl InstalledVersions {
+    /// Reads the saved registry. A missing or unreadable file starts empty.
+    pub fn load() -> Self {
+        fs::read_to_string(REGISTRY_FILE)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Records `version` of `component`, keeping the original install time
+    /// when the version hasn't changed, and saves the registry. Returns
+    /// whether the version changed.
+    pub fn record(&mut self, component: &str, version: &str) -> bool {
+        if self
+            .components
+            .get(component)
+            .is_some_and(|installed| installed.version == version)
+        {
+            return false;
+        }
+        self.components.insert(
+            component.to_string(),
+            InstalledComponent {
+                version: version.to_string(),
+                installed_at: now(),
+            },
+        );
+        if let Err(e) = self.save() {
+            warn!("failed to save {REGISTRY_FILE}: {e}");
+        }
+        true
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(REGISTRY_FILE, content).map_err(|e| e.to_string())
+    }
+
+    /// When `version` of `component` was installed, if it is the recorded
+    /// version.
+    pub fn installed_at(&self, component: &str, version: &str) -> Option<u64> {
+        self.components
+            .get(component)
+            .filter(|installed| installed.version == version)
+            .map(|installed| installed.installed_at)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &InstalledComponent)> {
+        self.components
+            .iter()
+            .map(|(component, installed)| (component.as_str(), installed))
+    }
+
+    /// E.g. `roslyn 4.12.0, installed 2024-06-02`.
+    pub fn describe(&self, component: &str) -> Option<String> {
+        let installed = self.components.get(component)?;
+        Some(format!(
+            "{component} {}, installed {}",
+            installed.version,
+            format_date(installed.installed_at)
+        ))
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Formats a Unix timestamp as a UTC `YYYY-MM-DD` date.
+pub fn format_date(timestamp: u64) -> String {
+    // Howard Hinnant's days-to-civil algorithm.
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_709_251_199), "2024-02-29");
+        assert_eq!(format_date(1_735_689_600), "2025-01-01");
+    }
+}
@@ -2,7 +2,12 @@ use std::fs;
 
 use zed_extension_api::{self as zed, settings::LspSettings, LanguageServerId, Result};
 
-use crate::language_servers::{nuget::NuGetClient, util};
+use crate::dotnet::DotnetLocator;
+use crate::language_servers::{
+    nuget::{self, NuGetClient},
+    util,
+};
+use crate::settings::CsharpSettings;
 
 const PACKAGE_ID: &str = "csharp-ls";
 const SERVER_DLL: &str = "CSharpLanguageServer.dll";
@@ -11,6 +16,7 @@ or set `lsp.csharp-ls.binary.path` to a working `csharp-ls` binary.";
 
 pub struct CsharpLs {
     cached_dll_path: Option<String>,
+    installed_version: Option<String>,
     nuget: NuGetClient,
 }
 
@@ -21,6 +27,7 @@ impl CsharpLs {
     pub fn new() -> Self {
         Self {
             cached_dll_path: None,
+            installed_version: None,
             nuget: NuGetClient::new(),
         }
     }
@@ -29,6 +36,7 @@ impl CsharpLs {
         &mut self,
         language_server_id: &LanguageServerId,
         worktree: &zed::Worktree,
+        dotnet: &mut DotnetLocator,
     ) -> Result<zed::Command> {
         let binary_settings = LspSettings::for_worktree(Self::LANGUAGE_SERVER_ID, worktree)
             .ok()
@@ -51,9 +59,13 @@ impl CsharpLs {
             });
         }
 
+        let settings = CsharpSettings::for_worktree(worktree);
+        let pinned_version = settings.pinned_version(Self::LANGUAGE_SERVER_ID);
         if let Some(ref dll_path) = self.cached_dll_path {
-            if fs::metadata(dll_path).is_ok_and(|s| s.is_file()) {
-                return Self::dotnet_exec(worktree, dll_path, binary_args);
+            if util::reuses_cached_install(pinned_version, self.installed_version.as_deref())
+                && fs::metadata(dll_path).is_ok_and(|s| s.is_file())
+            {
+                return Self::dotnet_exec(worktree, dotnet, dll_path, binary_args);
             }
         }
 
@@ -62,34 +74,93 @@ impl CsharpLs {
             &zed::LanguageServerInstallationStatus::CheckingForUpdate,
         );
 
-        let version = self.nuget.get_latest_version(PACKAGE_ID)?;
-        let version_dir = format!("{}-{}", Self::LANGUAGE_SERVER_ID, version);
+        self.nuget.apply_settings(&settings);
+        let version = match pinned_version {
+            Some(pinned) => pinned.to_string(),
+            None => match self
+                .nuget
+                .get_latest_version(PACKAGE_ID, &settings.excluded_version_patterns)
+            {
+                Ok(version) => version,
+                // Offline: start the newest install that is still complete.
+                Err(e) if e.is_offline() => {
+                    let versions_dir = nuget::package_versions_dir(PACKAGE_ID);
+                    let Some(install) = util::newest_local_install(&versions_dir, "", |dir| {
+                        Self::find_dll(dir).is_ok()
+                    }) else {
+                        return Err(e.into());
+                    };
+                    warn!("{e}; starting the installed csharp-ls {}", install.version);
+                    install.version
+                }
+                Err(e) => return Err(e.into()),
+            },
+        };
+        let version_dir = nuget::package_dir(PACKAGE_ID, &version);
 
         if Self::find_dll(&version_dir).is_err() {
-            zed::set_language_server_installation_status(
-                language_server_id,
-                &zed::LanguageServerInstallationStatus::Downloading,
-            );
-
-            self.nuget
-                .download_and_extract(PACKAGE_ID, &version, &version_dir)?;
-
-            util::remove_outdated_versions(Self::LANGUAGE_SERVER_ID, &version_dir)?;
+            let _lock = util::InstallLock::acquire(Self::LANGUAGE_SERVER_ID)?;
+            // Another Zed instance may have installed it while we waited.
+            if Self::find_dll(&version_dir).is_err() {
+                zed::set_language_server_installation_status(
+                    language_server_id,
+                    &zed::LanguageServerInstallationStatus::Downloading,
+                );
+
+                if pinned_version.is_some() {
+                    self.nuget.ensure_version_exists(PACKAGE_ID, &version)?;
+                }
+                self.nuget
+                    .download_and_extract(PACKAGE_ID, &version, &version_dir)?;
+
+                util::remove_outdated_package_versions(
+                    worktree,
+                    Self::LANGUAGE_SERVER_ID,
+                    &version_dir,
+                )?;
+            }
         }
 
         let dll_path = Self::find_dll(&version_dir)?;
-        let command = Self::dotnet_exec(worktree, &dll_path, binary_args)?;
+        info!("starting csharp-ls {version} from '{dll_path}'");
+        let command = Self::dotnet_exec(worktree, dotnet, &dll_path, binary_args)?;
         self.cached_dll_path = Some(dll_path);
+        self.installed_version = Some(version);
         Ok(command)
     }
 
+    /// The published versions of csharp-ls, newest first, for choosing one to
+    /// pin in `server_versions`.
+    pub fn available_versions(nuget: &mut NuGetClient) -> Result<Vec<String>> {
+        Ok(nuget.list_versions(PACKAGE_ID)?)
+    }
+
+    /// The version directory of the running install, which cache cleanup
+    /// must keep.
+    pub fn install_dir(&self) -> Option<String> {
+        self.installed_version
+            .as_deref()
+            .map(|version| nuget::package_dir(PACKAGE_ID, version))
+    }
+
+    /// Where `version` is installed.
+    pub fn version_dir(version: &str) -> String {
+        nuget::package_dir(PACKAGE_ID, version)
+    }
+
+    /// The package version the extension last installed or found installed.
+    pub fn installed_version(&self) -> Option<&str> {
+        self.installed_version.as_deref()
+    }
+
     fn dotnet_exec(
         worktree: &zed::Worktree,
+        locator: &mut DotnetLocator,
         dll_path: &str,
         user_args: Option<Vec<String>>,
     ) -> Result<zed::Command> {
-        let dotnet = worktree
-            .which("dotnet")
+        let dotnet = locator
+            .find(worktree)
             .ok_or_else(|| DOTNET_HINT.to_string())?;
         let mut args = vec!["exec".to_string(), dll_path.to_string()];
         if let Some(user) = user_args {
@@ -1,81 +1,258 @@
 use std::cmp::Ordering;
+use std::fs;
 
-use zed_extension_api::{self as zed, http_client, serde_json, Result};
+use zed_extension_api::{self as zed, http_client, serde_json};
+
+use crate::error::{Error, Result};
+use crate::http::{self, RetryPolicy};
+use crate::settings::{AutoDownload, CsharpSettings, Redirects};
 
 const ROSLYN_NUGET_FEED_INDEX: &str = "https://api.nuget.org/v3/index.json";
 
+/// The last service index that parsed, kept for when the feed returns a
+/// broken one.
+pub(crate) const SERVICE_INDEX_CACHE: &str = "nuget-service-index.json";
+
+/// The directory in the extension's working directory that holds extracted
+/// NuGet packages, laid out as `nuget/<package>/<version>`.
+pub const PACKAGES_DIR: &str = "nuget";
+
+/// Where `version` of `package_id` is installed. Both parts are lowercased,
+/// matching the flat container URLs.
+pub fn package_dir(package_id: &str, version: &str) -> String {
+    format!(
+        "{}/{}",
+        package_versions_dir(package_id),
+        version.to_lowercase()
+    )
+}
+
+/// The directory holding every installed version of `package_id`.
+pub fn package_versions_dir(package_id: &str) -> String {
+    format!("{PACKAGES_DIR}/{}", package_id.to_lowercase())
+}
+
 pub struct NuGetClient {
-    package_base_address: Option<String>,
+    service_index: Option<serde_json::Value>,
+    headers: Vec<(String, String)>,
+    extra_headers: Vec<(String, String)>,
+    auto_download: AutoDownload,
+    redirects: Redirects,
+}
+
+pub struct PackageSearchResult {
+    pub id: String,
+    pub latest_version: String,
+    pub description: String,
+    pub total_downloads: u64,
+    pub verified: bool,
 }
 
 impl NuGetClient {
     pub fn new() -> Self {
         NuGetClient {
-            package_base_address: None,
+            service_index: None,
+            headers: vec![
+                ("User-Agent".to_string(), http::build_user_agent()),
+                ("Accept".to_string(), "application/json".to_string()),
+            ],
+            extra_headers: Vec::new(),
+            auto_download: AutoDownload::default(),
+            redirects: Redirects::default(),
         }
     }
 
-    fn ensure_package_base_address(&mut self) -> Result<String> {
-        if let Some(ref base) = self.package_base_address {
-            return Ok(base.clone());
+    /// Applies the extension settings that affect requests and downloads:
+    /// `http_headers`, `auto_download` and `redirects`.
+    pub fn apply_settings(&mut self, settings: &CsharpSettings) {
+        self.extra_headers = settings
+            .http_headers
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        self.auto_download = settings.auto_download;
+        self.redirects = settings.redirects;
+    }
+
+    fn get(&self, url: &str, retry_policy: &RetryPolicy) -> Result<http_client::HttpResponse> {
+        let headers: Vec<_> = self
+            .headers
+            .iter()
+            .chain(&self.extra_headers)
+            .cloned()
+            .collect();
+        retry_policy.execute(|| http::get(url, headers.clone(), self.redirects))
+    }
+
+    /// Looks up `resource_type` in the service index, fetching the index
+    /// again when it is missing or lacks the resource.
+    ///
+    /// CDN hiccups can return truncated JSON, so parse failures and missing
+    /// resources are retried like network errors. If every attempt fails, the
+    /// last good index saved on disk is used instead.
+    fn service_resource(&mut self, resource_type: &str) -> Result<String> {
+        if let Some(url) = self
+            .service_index
+            .as_ref()
+            .and_then(|index| find_resource(index, resource_type))
+        {
+            return Ok(url);
         }
 
-        let response = http_client::fetch(
-            &http_client::HttpRequest::builder()
-                .method(http_client::HttpMethod::Get)
-                .url(ROSLYN_NUGET_FEED_INDEX)
-                .redirect_policy(http_client::RedirectPolicy::FollowAll)
-                .build()?,
-        )?;
+        let fetched = RetryPolicy::default().execute(|| {
+            let response = self.get(ROSLYN_NUGET_FEED_INDEX, &RetryPolicy::none())?;
+            let index: serde_json::Value = serde_json::from_slice(&response.body)
+                .map_err(|e| Error::corrupted("failed to parse NuGet service index", e))?;
+            let url = find_resource(&index, resource_type).ok_or_else(|| {
+                Error::NotFound(format!("{resource_type} not found in NuGet service index"))
+            })?;
+            Ok((index, url))
+        });
 
-        let index: serde_json::Value = serde_json::from_slice(&response.body)
-            .map_err(|e| format!("failed to parse NuGet service index: {e}"))?;
+        let (index, url) = match fetched {
+            Ok((index, url)) => {
+                if let Ok(content) = serde_json::to_vec(&index) {
+                    fs::write(SERVICE_INDEX_CACHE, content).ok();
+                }
+                (index, url)
+            }
+            Err(e) => {
+                let Some((index, url)) = fs::read(SERVICE_INDEX_CACHE)
+                    .ok()
+                    .and_then(|content| serde_json::from_slice(&content).ok())
+                    .and_then(|index| {
+                        let url = find_resource(&index, resource_type)?;
+                        Some((index, url))
+                    })
+                else {
+                    return Err(e);
+                };
+                warn!("{e}; using the cached NuGet service index");
+                (index, url)
+            }
+        };
+        self.service_index = Some(index);
+        Ok(url)
+    }
+
+    fn ensure_package_base_address(&mut self) -> Result<String> {
+        self.service_resource("PackageBaseAddress/3.0.0")
+    }
+
+    /// Searches for packages whose id or metadata matches `query`, most
+    /// downloaded first.
+    pub fn fuzzy_search(
+        &mut self,
+        query: &str,
+        take: u32,
+        include_prerelease: bool,
+    ) -> Result<Vec<PackageSearchResult>> {
+        let base = self.service_resource("SearchQueryService/3.5.0")?;
+        let url = format!(
+            "{base}?q={}&take={take}&prerelease={include_prerelease}&semVerLevel=2.0.0",
+            encode_query_component(query)
+        );
+        let response = self.get(&url, &RetryPolicy::none())?;
+
+        let body: serde_json::Value = serde_json::from_slice(&response.body).map_err(|e| {
+            Error::corrupted(
+                format!("failed to parse NuGet search results for '{query}'"),
+                e,
+            )
+        })?;
 
-        let base_url = index["resources"]
-            .as_array()
-            .ok_or("invalid NuGet service index: missing 'resources' array")?
+        let results = body["data"].as_array().ok_or_else(|| {
+            Error::Corrupted(format!(
+                "no data array in NuGet search results for '{query}'"
+            ))
+        })?;
+
+        let mut results: Vec<_> = results
             .iter()
-            .find(|r| {
-                r["@type"]
-                    .as_str()
-                    .is_some_and(|t| t == "PackageBaseAddress/3.0.0")
+            .filter_map(|result| {
+                Some(PackageSearchResult {
+                    id: result["id"].as_str()?.to_string(),
+                    latest_version: result["version"].as_str()?.to_string(),
+                    description: result["description"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                    total_downloads: result["totalDownloads"].as_u64().unwrap_or_default(),
+                    verified: result["verified"].as_bool().unwrap_or_default(),
+                })
+            })
+            .collect();
+        results.sort_by_key(|result| std::cmp::Reverse(result.total_downloads));
+        Ok(results)
+    }
+
+    /// Returns the newest published version, skipping versions that contain
+    /// any of `excluded_patterns` (case-insensitive), e.g. `rc` or `beta`.
+    pub fn get_latest_version(
+        &mut self,
+        package_id: &str,
+        excluded_patterns: &[String],
+    ) -> Result<String> {
+        self.parsed_versions(package_id)?
+            .into_iter()
+            .filter(|v| {
+                let v = v.raw.to_lowercase();
+                !excluded_patterns
+                    .iter()
+                    .any(|pattern| v.contains(&pattern.to_lowercase()))
+            })
+            .max()
+            .map(|v| v.raw)
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "no parseable versions found for NuGet package '{package_id}'"
+                ))
             })
-            .and_then(|r| r["@id"].as_str())
-            .ok_or("PackageBaseAddress/3.0.0 not found in NuGet service index")?
-            .trim_end_matches('/')
-            .to_string();
+    }
 
-        self.package_base_address = Some(base_url.clone());
-        Ok(base_url)
+    /// Every published version of `package_id`, newest first, for choosing a
+    /// version to pin.
+    pub fn list_versions(&mut self, package_id: &str) -> Result<Vec<String>> {
+        let mut versions = self.parsed_versions(package_id)?;
+        versions.sort_by(|a, b| b.cmp(a));
+        Ok(versions.into_iter().map(|v| v.raw).collect())
     }
 
-    pub fn get_latest_version(&mut self, package_id: &str) -> Result<String> {
+    /// Fails with a `server_versions` configuration error, listing the
+    /// newest published versions, when `version` of `package_id` isn't on
+    /// the feed, e.g. because a pin has a typo or the version was unlisted.
+    pub fn ensure_version_exists(&mut self, package_id: &str, version: &str) -> Result<()> {
+        let versions = self.parsed_versions(package_id)?;
+        check_version_listed(package_id, version, versions)
+    }
+
+    /// The versions in the package's flat container index. Unparseable
+    /// versions are skipped.
+    fn parsed_versions(&mut self, package_id: &str) -> Result<Vec<NuGetVersion>> {
         let base = self.ensure_package_base_address()?;
         let lower_id = package_id.to_lowercase();
 
         let url = format!("{base}/{lower_id}/index.json");
-        let response = http_client::fetch(
-            &http_client::HttpRequest::builder()
-                .method(http_client::HttpMethod::Get)
-                .url(&url)
-                .redirect_policy(http_client::RedirectPolicy::FollowAll)
-                .build()?,
-        )?;
-
-        let body: serde_json::Value = serde_json::from_slice(&response.body)
-            .map_err(|e| format!("failed to parse NuGet version index for '{package_id}': {e}"))?;
-
-        let versions = body["versions"]
-            .as_array()
-            .ok_or_else(|| format!("no versions array for NuGet package '{package_id}'"))?;
-
-        versions
+        let response = self.get(&url, &RetryPolicy::default())?;
+
+        let body: serde_json::Value = serde_json::from_slice(&response.body).map_err(|e| {
+            Error::corrupted(
+                format!("failed to parse NuGet version index for '{package_id}'"),
+                e,
+            )
+        })?;
+
+        let versions = body["versions"].as_array().ok_or_else(|| {
+            Error::Corrupted(format!(
+                "no versions array for NuGet package '{package_id}'"
+            ))
+        })?;
+
+        Ok(versions
             .iter()
             .filter_map(|v| v.as_str())
             .filter_map(NuGetVersion::parse)
-            .max()
-            .map(|v| v.raw)
-            .ok_or_else(|| format!("no parseable versions found for NuGet package '{package_id}'"))
+            .collect())
     }
 
     pub fn download_and_extract(
@@ -90,19 +267,78 @@ impl NuGetClient {
 
         let url = format!("{base}/{lower_id}/{lower_version}/{lower_id}.{lower_version}.nupkg");
 
-        zed::download_file(&url, dest_dir, zed::DownloadedFileType::Zip)
-            .map_err(|e| format!("failed to download NuGet package '{package_id}' v{version}: {e}"))
+        http::download_file(
+            self.auto_download,
+            &format!("NuGet package '{package_id}' v{version}"),
+            &url,
+            dest_dir,
+            zed::DownloadedFileType::Zip,
+            &RetryPolicy::aggressive(),
+        )
     }
 }
 
+/// `NuGetClient::ensure_version_exists` for the `versions` already fetched
+/// from the feed.
+fn check_version_listed(
+    package_id: &str,
+    version: &str,
+    mut versions: Vec<NuGetVersion>,
+) -> Result<()> {
+    const SHOWN: usize = 5;
+    let wanted = NuGetVersion::parse(version);
+    if versions
+        .iter()
+        .any(|v| wanted.as_ref() == Some(v) || v.raw.eq_ignore_ascii_case(version))
+    {
+        return Ok(());
+    }
+
+    versions.sort_by(|a, b| b.cmp(a));
+    let available: Vec<_> = versions
+        .iter()
+        .take(SHOWN)
+        .map(|v| v.raw.as_str())
+        .collect();
+    Err(Error::configuration(
+        "server_versions",
+        format!(
+            "version '{version}' not found for '{package_id}'; available: {}{}",
+            available.join(", "),
+            if versions.len() > SHOWN { ", ..." } else { "" }
+        ),
+    ))
+}
+
+fn find_resource(index: &serde_json::Value, resource_type: &str) -> Option<String> {
+    index["resources"]
+        .as_array()?
+        .iter()
+        .find(|r| r["@type"].as_str().is_some_and(|t| t == resource_type))
+        .and_then(|r| r["@id"].as_str())
+        .map(|url| url.trim_end_matches('/').to_string())
+}
+
+fn encode_query_component(input: &str) -> String {
+    input
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone)]
-struct NuGetVersion {
+pub(super) struct NuGetVersion {
     major: u64,
     minor: u64,
     patch: u64,
     revision: u64,
     prerelease: Option<String>,
-    raw: String,
+    pub(super) raw: String,
 }
 
 impl PartialEq for NuGetVersion {
@@ -114,10 +350,16 @@ impl PartialEq for NuGetVersion {
 impl Eq for NuGetVersion {}
 
 impl NuGetVersion {
-    fn parse(input: &str) -> Option<Self> {
-        let (core, prerelease) = match input.split_once('-') {
+    /// Parses `1.2`, `1.2.3.4` or `1.2.3-beta.1`, with an optional leading
+    /// `v` as in GitHub release tags.
+    pub(super) fn parse(input: &str) -> Option<Self> {
+        let unprefixed = input
+            .strip_prefix(['v', 'V'])
+            .filter(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(input);
+        let (core, prerelease) = match unprefixed.split_once('-') {
             Some((c, p)) => (c, Some(p.to_string())),
-            None => (input, None),
+            None => (unprefixed, None),
         };
 
         let segments: Vec<u64> = core
@@ -190,3 +432,74 @@ impl PartialOrd for NuGetVersion {
         Some(self.cmp(other))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(input: &str) -> NuGetVersion {
+        NuGetVersion::parse(input).unwrap_or_else(|| panic!("failed to parse '{input}'"))
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(version("1.2"), version("1.2.0.0"));
+        assert_eq!(version("v1.39.12").raw, "v1.39.12");
+        assert_eq!(version("v1.39.12"), version("1.39.12"));
+        assert_eq!(
+            version("5.0.0-1.25277.114").prerelease.as_deref(),
+            Some("1.25277.114")
+        );
+        assert!(NuGetVersion::parse("latest").is_none());
+        assert!(NuGetVersion::parse("1.2.3.4.5").is_none());
+        assert!(NuGetVersion::parse("vnext").is_none());
+    }
+
+    #[test]
+    fn orders_versions() {
+        assert!(version("1.10.0") > version("1.9.0"));
+        assert!(version("1.2.3.1") > version("1.2.3"));
+        assert!(version("1.0.0") > version("1.0.0-rc.1"));
+        assert!(version("1.0.0-rc.10") > version("1.0.0-rc.2"));
+        assert!(version("1.0.0-rc.1") > version("1.0.0-beta.9"));
+        assert!(version("1.0.0-beta.1") > version("1.0.0-beta"));
+        assert!(version("1.0.0-alpha") > version("1.0.0-1"));
+        assert_eq!(version("1.0.0-RC.1"), version("1.0.0-rc.1"));
+    }
+
+    #[test]
+    fn lists_the_newest_versions_when_a_pin_is_missing() {
+        let versions = |raw: &[&str]| raw.iter().map(|v| version(v)).collect::<Vec<_>>();
+        let published = versions(&[
+            "0.18.0",
+            "0.20.0",
+            "0.19.0-rc.1",
+            "0.9.9",
+            "0.19.0",
+            "0.17.0",
+        ]);
+
+        assert!(check_version_listed("csharp-ls", "0.19.0-RC.1", published.clone()).is_ok());
+        assert!(check_version_listed("csharp-ls", "0.20", published.clone()).is_ok());
+
+        match check_version_listed("csharp-ls", "0.21.0", published) {
+            Err(Error::Configuration { setting, message }) => {
+                assert_eq!(setting, "server_versions");
+                assert_eq!(
+                    message,
+                    "version '0.21.0' not found for 'csharp-ls'; \
+                     available: 0.20.0, 0.19.0, 0.19.0-rc.1, 0.18.0, 0.17.0, ..."
+                );
+            }
+            result => panic!("expected a configuration error, got {result:?}"),
+        }
+
+        match check_version_listed("csharp-ls", "1.0.0", versions(&["0.2.0", "0.1.0"])) {
+            Err(error) => assert_eq!(
+                error.to_string(),
+                "version '1.0.0' not found for 'csharp-ls'; available: 0.2.0, 0.1.0"
+            ),
+            Ok(()) => panic!("1.0.0 isn't published"),
+        }
+    }
+}
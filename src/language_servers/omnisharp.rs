@@ -1,15 +1,160 @@
 use std::fs;
+use std::time::Duration;
+
 use zed_extension_api::{self as zed, settings::LspSettings, LanguageServerId, Result};
 
-use crate::language_servers::util;
+use crate::dotnet::{self, DotnetLocator};
+use crate::http::{self, RetryPolicy};
+use crate::language_servers::{nuget::NuGetVersion, util};
+use crate::project;
+use crate::settings::{CsharpSettings, OmnisharpExtensionSettings};
+
+/// How long the latest OmniSharp release is trusted before GitHub is asked
+/// again. `/csharp-reinstall` clears the cache.
+const RELEASE_CACHE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// OmniSharp releases known to misbehave with some .NET SDKs.
+struct IncompatibleVersion {
+    /// The newest affected release; older ones are affected too.
+    server_version: &'static str,
+    /// Either a prefix of the SDK version, e.g. `8.0.100-preview`, or `>=`
+    /// followed by the oldest affected SDK version.
+    sdk_version_pattern: &'static str,
+    issue_url: &'static str,
+    message: &'static str,
+}
+
+impl IncompatibleVersion {
+    fn affects(&self, server_version: &str, sdk_version: &str) -> bool {
+        let (Some(server), Some(newest_affected)) = (
+            NuGetVersion::parse(server_version),
+            NuGetVersion::parse(self.server_version),
+        ) else {
+            return false;
+        };
+        server <= newest_affected && self.matches_sdk(sdk_version)
+    }
+
+    fn matches_sdk(&self, sdk_version: &str) -> bool {
+        match self.sdk_version_pattern.strip_prefix(">=") {
+            Some(oldest_affected) => NuGetVersion::parse(sdk_version)
+                .zip(NuGetVersion::parse(oldest_affected))
+                .is_some_and(|(sdk, oldest_affected)| sdk >= oldest_affected),
+            None => sdk_version.starts_with(self.sdk_version_pattern),
+        }
+    }
+}
+
+/// Known-bad OmniSharp/SDK combinations. Matches only produce a warning; the
+/// server still starts.
+const OMNISHARP_INCOMPATIBLE_VERSIONS: &[IncompatibleVersion] = &[IncompatibleVersion {
+    server_version: "1.39.5",
+    sdk_version_pattern: ">=8.0.100-preview",
+    issue_url: "https://github.com/OmniSharp/omnisharp-roslyn/issues?q=is%3Aissue+%22.NET+8%22",
+    message: "this OmniSharp predates .NET 8 and may fail to load projects; update OmniSharp",
+}];
 
 pub struct Omnisharp {
-    cached_binary_path: Option<String>,
+    cached_binary: Option<(OmnisharpFlavor, String)>,
+    installed_version: Option<String>,
+}
+
+/// Which OmniSharp build to install.
+#[derive(Clone, Copy, PartialEq)]
+enum OmnisharpFlavor {
+    /// Runs on .NET 6 or later. Can't evaluate most .NET Framework projects.
+    Net6,
+    /// Runs on .NET Framework on Windows and on the bundled mono elsewhere.
+    Framework,
+}
+
+impl OmnisharpFlavor {
+    /// Picks the Framework build for Unity projects and when the worktree's
+    /// `Directory.Build.props` targets only .NET Framework, and warns when it
+    /// mixes both.
+    ///
+    /// Extensions can't list the worktree, so individual project files aren't
+    /// inspected.
+    fn for_worktree(worktree: &zed::Worktree) -> Self {
+        if let Some(unity) = project::find_unity_project(worktree) {
+            info!(
+                "detected Unity {}; using the .NET Framework build of OmniSharp",
+                unity
+                    .editor_version
+                    .as_deref()
+                    .unwrap_or("(unknown version)")
+            );
+            return Self::Framework;
+        }
+
+        let Some(props) = dotnet::find_directory_build_props(worktree) else {
+            return Self::Net6;
+        };
+        let legacy = props.net_framework_targets();
+        if legacy.is_empty() {
+            Self::Net6
+        } else if props.targets_only_net_framework() {
+            warn!(
+                "Directory.Build.props targets only .NET Framework ({}); \
+                 using the .NET Framework build of OmniSharp",
+                legacy.join(", ")
+            );
+            Self::Framework
+        } else {
+            warn!(
+                "Directory.Build.props also targets .NET Framework ({}); \
+                 projects for those targets may not load",
+                legacy.join(", ")
+            );
+            Self::Net6
+        }
+    }
+
+    /// E.g. `omnisharp-linux-x64-net6.0.tar.gz`. The mono build for macOS is
+    /// a single universal `omnisharp-osx.tar.gz`.
+    fn asset_name(self, platform: zed::Os, arch: zed::Architecture) -> String {
+        let os = match platform {
+            zed::Os::Mac => "osx",
+            zed::Os::Linux => "linux",
+            zed::Os::Windows => "win",
+        };
+        let arch = match arch {
+            zed::Architecture::Aarch64 => "arm64",
+            zed::Architecture::X86 => "x86",
+            zed::Architecture::X8664 => "x64",
+        };
+        let extension = match platform {
+            zed::Os::Mac | zed::Os::Linux => "tar.gz",
+            zed::Os::Windows => "zip",
+        };
+        match (self, platform) {
+            (Self::Net6, _) => format!("omnisharp-{os}-{arch}-net6.0.{extension}"),
+            (Self::Framework, zed::Os::Mac) => format!("omnisharp-{os}.{extension}"),
+            (Self::Framework, _) => format!("omnisharp-{os}-{arch}.{extension}"),
+        }
+    }
+
+    fn version_dir(self, version: &str) -> String {
+        match self {
+            Self::Net6 => format!("{}-{version}", Omnisharp::LANGUAGE_SERVER_ID),
+            Self::Framework => format!("{}-framework-{version}", Omnisharp::LANGUAGE_SERVER_ID),
+        }
+    }
+
+    fn binary_path(self, version_dir: &str, platform: zed::Os) -> String {
+        match (self, platform) {
+            (_, zed::Os::Windows) => format!("{version_dir}/OmniSharp.exe"),
+            (Self::Net6, _) => format!("{version_dir}/OmniSharp"),
+            // The mono builds start through a script that sets up the
+            // bundled runtime.
+            (Self::Framework, _) => format!("{version_dir}/run"),
+        }
+    }
 }
 
 pub struct OmnisharpBinary {
     pub path: String,
-    pub args: Option<Vec<String>>,
+    pub args: Vec<String>,
 }
 
 impl Omnisharp {
@@ -17,7 +162,8 @@ impl Omnisharp {
 
     pub fn new() -> Self {
         Self {
-            cached_binary_path: None,
+            cached_binary: None,
+            installed_version: None,
         }
     }
 
@@ -25,13 +171,19 @@ impl Omnisharp {
         &mut self,
         language_server_id: &LanguageServerId,
         worktree: &zed::Worktree,
+        dotnet: &mut DotnetLocator,
     ) -> Result<OmnisharpBinary> {
-        let binary_settings = LspSettings::for_worktree("omnisharp", worktree)
-            .ok()
-            .and_then(|lsp_settings| lsp_settings.binary);
-        let binary_args = binary_settings
-            .as_ref()
-            .and_then(|binary_settings| binary_settings.arguments.clone());
+        let (binary_settings, settings) =
+            match LspSettings::for_worktree(Self::LANGUAGE_SERVER_ID, worktree) {
+                Ok(lsp_settings) => (lsp_settings.binary, lsp_settings.settings),
+                Err(_) => (None, None),
+            };
+        let binary_args = Self::server_args(
+            binary_settings
+                .as_ref()
+                .and_then(|binary_settings| binary_settings.arguments.clone()),
+            settings,
+        );
 
         if let Some(path) = binary_settings.and_then(|binary_settings| binary_settings.path) {
             return Ok(OmnisharpBinary {
@@ -47,8 +199,9 @@ impl Omnisharp {
             });
         }
 
-        if let Some(path) = &self.cached_binary_path {
-            if fs::metadata(path).is_ok_and(|stat| stat.is_file()) {
+        let flavor = OmnisharpFlavor::for_worktree(worktree);
+        if let Some((cached_flavor, path)) = &self.cached_binary {
+            if *cached_flavor == flavor && fs::metadata(path).is_ok_and(|stat| stat.is_file()) {
                 return Ok(OmnisharpBinary {
                     path: path.clone(),
                     args: binary_args,
@@ -60,68 +213,469 @@ impl Omnisharp {
             language_server_id,
             &zed::LanguageServerInstallationStatus::CheckingForUpdate,
         );
-        let release = zed::latest_github_release(
+        let (platform, arch) = zed::current_platform();
+        let release = match util::cached_latest_release(
             "OmniSharp/omnisharp-roslyn",
             zed::GithubReleaseOptions {
                 require_assets: true,
                 pre_release: false,
             },
-        )?;
-
-        let (platform, arch) = zed::current_platform();
-        let asset_name = format!(
-            "omnisharp-{os}-{arch}-net6.0.{extension}",
-            os = match platform {
-                zed::Os::Mac => "osx",
-                zed::Os::Linux => "linux",
-                zed::Os::Windows => "win",
-            },
-            arch = match arch {
-                zed::Architecture::Aarch64 => "arm64",
-                zed::Architecture::X86 => "x86",
-                zed::Architecture::X8664 => "x64",
-            },
-            extension = match platform {
-                zed::Os::Mac | zed::Os::Linux => "tar.gz",
-                zed::Os::Windows => "zip",
+            RELEASE_CACHE_TTL,
+        ) {
+            Ok(release) => release,
+            // Offline with nothing cached: start the newest install of this
+            // flavor that is still complete.
+            Err(e) => {
+                let Some(install) =
+                    util::newest_local_install(".", &flavor.version_dir(""), |dir| {
+                        fs::metadata(flavor.binary_path(dir, platform))
+                            .is_ok_and(|stat| stat.is_file())
+                    })
+                else {
+                    return Err(e);
+                };
+                warn!("{e}; starting the installed OmniSharp {}", install.version);
+                let binary_path = flavor.binary_path(&install.path, platform);
+                self.cached_binary = Some((flavor, binary_path.clone()));
+                self.installed_version = Some(install.version);
+                return Ok(OmnisharpBinary {
+                    path: binary_path,
+                    args: binary_args,
+                });
             }
-        );
+        };
+
+        let asset_name = flavor.asset_name(platform, arch);
 
+        // Forks sometimes capitalize asset names differently. Only the whole
+        // name is compared, so a case-insensitive match can't pick up a
+        // different platform or target framework.
         let asset = release
             .assets
             .iter()
             .find(|asset| asset.name == asset_name)
-            .ok_or_else(|| format!("no asset found matching {:?}", asset_name))?;
+            .or_else(|| {
+                release
+                    .assets
+                    .iter()
+                    .find(|asset| asset.name.eq_ignore_ascii_case(&asset_name))
+            })
+            .ok_or_else(|| {
+                Self::missing_asset_message(platform, arch, &release.version, &asset_name)
+            })?;
 
-        let version_dir = format!("{}-{}", Self::LANGUAGE_SERVER_ID, release.version);
-        let binary_path = match platform {
-            zed::Os::Windows => format!("{version_dir}/OmniSharp.exe"),
-            _ => format!("{version_dir}/OmniSharp"),
-        };
+        info!(
+            "OmniSharp release {}: using asset '{}'",
+            release.version, asset.name
+        );
+        Self::warn_if_incompatible(worktree, dotnet, &release.version);
+
+        let version_dir = flavor.version_dir(&release.version);
+        let binary_path = flavor.binary_path(&version_dir, platform);
 
         if !fs::metadata(&binary_path).is_ok_and(|stat| stat.is_file()) {
-            zed::set_language_server_installation_status(
-                language_server_id,
-                &zed::LanguageServerInstallationStatus::Downloading,
-            );
+            let _lock = util::InstallLock::acquire(Self::LANGUAGE_SERVER_ID)?;
+            // Another Zed instance may have installed it while we waited.
+            if !fs::metadata(&binary_path).is_ok_and(|stat| stat.is_file()) {
+                zed::set_language_server_installation_status(
+                    language_server_id,
+                    &zed::LanguageServerInstallationStatus::Downloading,
+                );
 
-            zed::download_file(
-                &asset.download_url,
-                &version_dir,
-                match platform {
-                    zed::Os::Mac | zed::Os::Linux => zed::DownloadedFileType::GzipTar,
-                    zed::Os::Windows => zed::DownloadedFileType::Zip,
-                },
-            )
-            .map_err(|e| format!("failed to download file: {e}"))?;
+                http::download_file(
+                    CsharpSettings::for_worktree(worktree).auto_download,
+                    &format!("OmniSharp {}", release.version),
+                    &asset.download_url,
+                    &version_dir,
+                    match platform {
+                        zed::Os::Mac | zed::Os::Linux => zed::DownloadedFileType::GzipTar,
+                        zed::Os::Windows => zed::DownloadedFileType::Zip,
+                    },
+                    &RetryPolicy::none(),
+                )?;
 
-            util::remove_outdated_versions(Self::LANGUAGE_SERVER_ID, &version_dir)?;
+                // The mono and .NET Framework builds can't run on `dotnet`.
+                if flavor == OmnisharpFlavor::Net6 {
+                    util::verify_reported_version(
+                        worktree,
+                        dotnet,
+                        "OmniSharp",
+                        &format!("{version_dir}/OmniSharp.dll"),
+                        &release.version,
+                    );
+                }
+                util::remove_outdated_versions(
+                    worktree,
+                    Self::LANGUAGE_SERVER_ID,
+                    &flavor.version_dir(""),
+                    &version_dir,
+                )?;
+            }
         }
 
-        self.cached_binary_path = Some(binary_path.clone());
+        info!("starting OmniSharp from '{binary_path}'");
+        self.cached_binary = Some((flavor, binary_path.clone()));
+        self.installed_version = Some(release.version);
         Ok(OmnisharpBinary {
             path: binary_path,
             args: binary_args,
         })
     }
+
+    /// The version directory of the running install, which cache cleanup
+    /// must keep. `None` when OmniSharp wasn't installed by the extension.
+    pub fn install_dir(&self) -> Option<String> {
+        let (_, path) = self.cached_binary.as_ref()?;
+        let (dir, _) = path.split_once('/')?;
+        Some(dir.to_string())
+    }
+
+    /// Where `version` is installed, for each build.
+    pub fn version_dirs(version: &str) -> [String; 2] {
+        [OmnisharpFlavor::Net6, OmnisharpFlavor::Framework]
+            .map(|flavor| flavor.version_dir(version))
+    }
+
+    /// Why a release has no asset for this platform.
+    fn missing_asset_message(
+        platform: zed::Os,
+        arch: zed::Architecture,
+        release_version: &str,
+        asset_name: &str,
+    ) -> String {
+        match (platform, arch) {
+            // OmniSharp only publishes 32-bit builds for Windows.
+            (zed::Os::Mac | zed::Os::Linux, zed::Architecture::X86) => format!(
+                "OmniSharp has no 32-bit {} build; \
+                 use a 64-bit system or set `binary.path` to an OmniSharp you built",
+                if platform == zed::Os::Mac {
+                    "macOS"
+                } else {
+                    "Linux"
+                }
+            ),
+            (zed::Os::Windows, zed::Architecture::X86) => format!(
+                "OmniSharp release {release_version} has no 32-bit Windows build \
+                 ({asset_name:?}); install the 64-bit Zed build or set `binary.path`"
+            ),
+            _ => format!("no asset found matching {asset_name:?}"),
+        }
+    }
+
+    /// The release the extension last installed or found installed.
+    pub fn installed_version(&self) -> Option<&str> {
+        self.installed_version.as_deref()
+    }
+
+    fn warn_if_incompatible(
+        worktree: &zed::Worktree,
+        dotnet: &mut DotnetLocator,
+        server_version: &str,
+    ) {
+        let server_version = server_version.trim_start_matches('v');
+        let Some(install) = dotnet.install(worktree) else {
+            return;
+        };
+        for incompatible in OMNISHARP_INCOMPATIBLE_VERSIONS {
+            if let Some(sdk) = install
+                .sdks
+                .iter()
+                .find(|sdk| incompatible.affects(server_version, &sdk.version))
+            {
+                warn!(
+                    "OmniSharp {server_version} with .NET SDK {}: {} ({})",
+                    sdk.version, incompatible.message, incompatible.issue_url
+                );
+                return;
+            }
+        }
+    }
+
+    /// The arguments OmniSharp is started with. `binary.arguments` replaces
+    /// them entirely, so leave out `-lsp` there only to run OmniSharp in a
+    /// different mode. Otherwise `settings.prepend_args` come first, followed
+    /// by `-lsp`.
+    fn server_args(
+        binary_args: Option<Vec<String>>,
+        settings: Option<zed::serde_json::Value>,
+    ) -> Vec<String> {
+        if let Some(args) = binary_args {
+            return args;
+        }
+        let extension_settings = match settings {
+            Some(zed::serde_json::Value::Object(mut settings_map)) => {
+                OmnisharpExtensionSettings::take_from(&mut settings_map).unwrap_or_default()
+            }
+            _ => OmnisharpExtensionSettings::default(),
+        };
+        let mut args = extension_settings.prepend_args;
+        args.push("-lsp".to_string());
+        args
+    }
+
+    pub fn configuration_options(
+        worktree: &zed::Worktree,
+    ) -> Result<Option<zed::serde_json::Value>> {
+        let mut settings = LspSettings::for_worktree(Self::LANGUAGE_SERVER_ID, worktree)
+            .ok()
+            .and_then(|lsp_settings| lsp_settings.settings);
+
+        let props = dotnet::find_directory_build_props(worktree);
+
+        // Default the target framework from a monorepo-wide Directory.Build.props so
+        // OmniSharp doesn't pick the wrong TFM for multi-targeted projects.
+        if let Some(target_framework) = props
+            .as_ref()
+            .and_then(|props| props.target_framework.clone())
+        {
+            let settings = settings.get_or_insert_with(|| zed::serde_json::json!({}));
+            if let Some(msbuild) = settings.as_object_mut().and_then(|s| section(s, "MsBuild")) {
+                msbuild
+                    .entry("TargetFramework")
+                    .or_insert(target_framework.into());
+            }
+        }
+
+        // OmniSharp ignores analyzers from package references unless asked to
+        // load them.
+        if props.is_some_and(|props| !props.analyzer_packages.is_empty()) {
+            let settings = settings.get_or_insert_with(|| zed::serde_json::json!({}));
+            if let Some(roslyn_extensions) = settings
+                .as_object_mut()
+                .and_then(|s| section(s, "RoslynExtensionsOptions"))
+            {
+                roslyn_extensions
+                    .entry("EnableAnalyzersSupport")
+                    .or_insert(true.into());
+            }
+        }
+
+        // The Godot SDK needs the target platform to evaluate projects.
+        if project::find_godot_project(worktree).is_some() {
+            let settings = settings.get_or_insert_with(|| zed::serde_json::json!({}));
+            if let Some(properties) = settings
+                .as_object_mut()
+                .and_then(|s| section(s, "msbuild_properties"))
+            {
+                properties
+                    .entry("GodotTargetPlatform")
+                    .or_insert(project::GodotProject::target_platform().into());
+            }
+        }
+
+        // Keep Unity's generated folders out of project discovery.
+        if project::find_unity_project(worktree).is_some() {
+            let settings = settings.get_or_insert_with(|| zed::serde_json::json!({}));
+            if let Some(file_options) = settings
+                .as_object_mut()
+                .and_then(|s| section(s, "FileOptions"))
+            {
+                file_options
+                    .entry("ExcludeSearchPatterns")
+                    .or_insert(project::UNITY_EXCLUDE_PATTERNS.into());
+            }
+        }
+
+        // Always send a configuration so the default exclusions apply.
+        let settings = settings.unwrap_or_else(|| zed::serde_json::json!({}));
+        Ok(Some(Self::transform_settings_for_omnisharp(settings)))
+    }
+
+    fn transform_settings_for_omnisharp(
+        mut settings: zed::serde_json::Value,
+    ) -> zed::serde_json::Value {
+        let Some(settings_map) = settings.as_object_mut() else {
+            return zed::serde_json::json!({ "omnisharp": settings });
+        };
+
+        let extension_settings = OmnisharpExtensionSettings::take_from(settings_map)
+            .unwrap_or_else(|e| {
+                warn!("{e}");
+                OmnisharpExtensionSettings::default()
+            });
+
+        // Move the MSBuild properties to where OmniSharp expects them.
+        if !extension_settings.msbuild_properties.is_empty() {
+            let properties: zed::serde_json::Map<_, _> = extension_settings
+                .msbuild_properties
+                .into_iter()
+                .filter(|(name, _)| {
+                    let valid = is_msbuild_identifier(name);
+                    if !valid {
+                        warn!("ignoring invalid MSBuild property name '{name}'");
+                    }
+                    valid
+                })
+                .map(|(name, value)| (name, value.into()))
+                .collect();
+
+            if let Some(msbuild) = section(settings_map, "MsBuild") {
+                msbuild.insert("MSBuildProperties".into(), properties.into());
+            }
+        }
+
+        // Limit project discovery to the given globs instead of the whole worktree.
+        if !extension_settings.project_roots.is_empty() {
+            if let Some(file_options) = section(settings_map, "FileOptions") {
+                file_options.insert("Include".into(), extension_settings.project_roots.into());
+            }
+        }
+
+        // Add the exclusions to any patterns that are already configured.
+        if !extension_settings.file_exclude_globs.is_empty() {
+            if let Some(patterns) = section(settings_map, "FileOptions")
+                .map(|file_options| {
+                    file_options
+                        .entry("ExcludeSearchPatterns")
+                        .or_insert_with(|| zed::serde_json::json!([]))
+                })
+                .and_then(|patterns| patterns.as_array_mut())
+            {
+                for glob in extension_settings.file_exclude_globs {
+                    let glob = zed::serde_json::Value::from(glob);
+                    if !patterns.contains(&glob) {
+                        patterns.push(glob);
+                    }
+                }
+            }
+        }
+
+        zed::serde_json::json!({ "omnisharp": settings })
+    }
+}
+
+/// Returns the nested object at `key`, creating it if it doesn't exist.
+fn section<'a>(
+    settings_map: &'a mut zed::serde_json::Map<String, zed::serde_json::Value>,
+    key: &str,
+) -> Option<&'a mut zed::serde_json::Map<String, zed::serde_json::Value>> {
+    settings_map
+        .entry(key)
+        .or_insert_with(|| zed::serde_json::json!({}))
+        .as_object_mut()
+}
+
+fn is_msbuild_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_old_releases_with_net8_and_later() {
+        let affected = |server: &str, sdk: &str| {
+            OMNISHARP_INCOMPATIBLE_VERSIONS
+                .iter()
+                .any(|incompatible| incompatible.affects(server, sdk))
+        };
+        assert!(affected("1.39.5", "8.0.100-preview.7.23376.3"));
+        assert!(affected("1.38.2", "9.0.100"));
+        assert!(affected("1.39.5", "10.0.100"));
+        assert!(affected("1.39.5", "11.0.100-preview.1"));
+        assert!(!affected("1.39.6", "8.0.100"));
+        assert!(!affected("1.39.5", "7.0.400"));
+        assert!(!affected("1.39.5", "1.0.0"));
+    }
+
+    #[test]
+    fn names_the_assets_of_each_build() {
+        use zed::{Architecture, Os};
+        let cases = [
+            (
+                OmnisharpFlavor::Net6,
+                Os::Mac,
+                Architecture::Aarch64,
+                "omnisharp-osx-arm64-net6.0.tar.gz",
+            ),
+            (
+                OmnisharpFlavor::Net6,
+                Os::Windows,
+                Architecture::X8664,
+                "omnisharp-win-x64-net6.0.zip",
+            ),
+            // Unity worktrees always use the Framework build.
+            (
+                OmnisharpFlavor::Framework,
+                Os::Mac,
+                Architecture::Aarch64,
+                "omnisharp-osx.tar.gz",
+            ),
+            (
+                OmnisharpFlavor::Framework,
+                Os::Mac,
+                Architecture::X8664,
+                "omnisharp-osx.tar.gz",
+            ),
+            (
+                OmnisharpFlavor::Framework,
+                Os::Linux,
+                Architecture::X8664,
+                "omnisharp-linux-x64.tar.gz",
+            ),
+            (
+                OmnisharpFlavor::Framework,
+                Os::Windows,
+                Architecture::X86,
+                "omnisharp-win-x86.zip",
+            ),
+        ];
+        for (flavor, os, arch, expected) in cases {
+            assert_eq!(flavor.asset_name(os, arch), expected);
+        }
+    }
+
+    #[test]
+    fn explains_missing_32_bit_builds() {
+        use zed::{Architecture, Os};
+        let message = Omnisharp::missing_asset_message(
+            Os::Linux,
+            Architecture::X86,
+            "v1.39.12",
+            "omnisharp-linux-x86-net6.0.tar.gz",
+        );
+        assert_eq!(
+            message,
+            "OmniSharp has no 32-bit Linux build; \
+             use a 64-bit system or set `binary.path` to an OmniSharp you built"
+        );
+        let message = Omnisharp::missing_asset_message(
+            Os::Windows,
+            Architecture::X86,
+            "v1.39.12",
+            "omnisharp-win-x86-net6.0.zip",
+        );
+        assert_eq!(
+            message,
+            "OmniSharp release v1.39.12 has no 32-bit Windows build \
+             (\"omnisharp-win-x86-net6.0.zip\"); install the 64-bit Zed build or set `binary.path`"
+        );
+        let message = Omnisharp::missing_asset_message(
+            Os::Mac,
+            Architecture::Aarch64,
+            "v1.39.12",
+            "omnisharp-osx-arm64-net6.0.tar.gz",
+        );
+        assert_eq!(
+            message,
+            "no asset found matching \"omnisharp-osx-arm64-net6.0.tar.gz\""
+        );
+    }
+
+    #[test]
+    fn builds_install_side_by_side() {
+        let [net6, framework] = Omnisharp::version_dirs("1.39.12");
+        assert_eq!(net6, "omnisharp-1.39.12");
+        assert_eq!(framework, "omnisharp-framework-1.39.12");
+        // Cleanup removes `<prefix><version>`, so neither build's prefix
+        // matches the other's directories.
+        let net6_prefix = OmnisharpFlavor::Net6.version_dir("");
+        assert!(NuGetVersion::parse(framework.strip_prefix(&net6_prefix).unwrap()).is_none());
+        assert!(!net6.starts_with(&OmnisharpFlavor::Framework.version_dir("")));
+    }
 }
@@ -1,23 +1,69 @@
 use std::fs;
 
-use zed_extension_api::{self as zed, settings::LspSettings, LanguageServerId, Result};
+use zed_extension_api::{self as zed, settings::LspSettings, LanguageServerId};
 
-use crate::language_servers::{nuget::NuGetClient, util};
+use crate::dotnet::{self, DotnetLocator};
+use crate::error::{Error, Result};
+use crate::installed::{self, InstalledVersions};
+use crate::language_servers::{
+    nuget::{self, NuGetClient},
+    util,
+};
+use crate::settings::CsharpSettings;
 
 const PACKAGE_PREFIX: &str = "roslyn-language-server";
 const SERVER_BINARY: &str = "Microsoft.CodeAnalysis.LanguageServer";
 
 pub struct Roslyn {
-    cached_server_path: Option<ServerPath>,
+    cached_binary: Option<RoslynBinaryInfo>,
     nuget: NuGetClient,
 }
 
+/// The server install the extension last started.
+pub struct RoslynBinaryInfo {
+    server_path: ServerPath,
+    pub package_id: String,
+    pub version: String,
+    /// Seconds since the Unix epoch. `None` for an install from before the
+    /// extension recorded install times.
+    pub installed_at: Option<u64>,
+    pub source: PackageSource,
+}
+
+/// How the started Roslyn package was obtained.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PackageSource {
+    /// Downloaded from the NuGet feed for this start.
+    Downloaded,
+    /// Already installed, and the latest or pinned version.
+    Installed,
+    /// The newest complete install, started because the feed couldn't be
+    /// reached.
+    OfflineFallback,
+}
+
+impl PackageSource {
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Downloaded => "downloaded from NuGet",
+            Self::Installed => "already installed",
+            Self::OfflineFallback => "installed, started offline",
+        }
+    }
+}
+
+impl RoslynBinaryInfo {
+    pub fn path(&self) -> &str {
+        self.server_path.as_str()
+    }
+}
+
 impl Roslyn {
     pub const LANGUAGE_SERVER_ID: &'static str = "roslyn";
 
     pub fn new() -> Self {
         Roslyn {
-            cached_server_path: None,
+            cached_binary: None,
             nuget: NuGetClient::new(),
         }
     }
@@ -26,6 +72,8 @@ impl Roslyn {
         &mut self,
         language_server_id: &LanguageServerId,
         worktree: &zed::Worktree,
+        dotnet: &mut DotnetLocator,
+        installed: &InstalledVersions,
     ) -> Result<zed::Command> {
         let binary_settings = LspSettings::for_worktree(Self::LANGUAGE_SERVER_ID, worktree)
             .ok()
@@ -42,18 +90,137 @@ impl Roslyn {
             });
         }
 
-        if let Some(ref server_path) = self.cached_server_path {
-            if fs::metadata(server_path.as_str()).is_ok_and(|stat| stat.is_file()) {
-                return Ok(Self::build_command(server_path, binary_args));
+        let settings = CsharpSettings::for_worktree(worktree);
+        if let Some(path) = Self::repo_server(worktree, &settings) {
+            info!("starting the repository's Roslyn server '{path}'");
+            return Self::build_command(worktree, dotnet, &ServerPath::Exe(path), binary_args);
+        }
+
+        let pinned_version = settings.pinned_version(Self::LANGUAGE_SERVER_ID);
+        if let Some(ref binary) = self.cached_binary {
+            if util::reuses_cached_install(pinned_version, Some(&binary.version))
+                && fs::metadata(binary.path()).is_ok_and(|stat| stat.is_file())
+            {
+                return Self::build_command(worktree, dotnet, &binary.server_path, binary_args);
             }
         }
 
+        if dotnet::find_directory_build_props(worktree)
+            .is_some_and(|props| props.targets_only_net_framework())
+        {
+            warn!(
+                "Directory.Build.props targets only .NET Framework, which Roslyn usually can't \
+                 load; use OmniSharp instead with \
+                 `\"languages\": {{ \"CSharp\": {{ \"language_servers\": [\"omnisharp\", \"!roslyn\"] }} }}`"
+            );
+        }
+
         zed::set_language_server_installation_status(
             language_server_id,
             &zed::LanguageServerInstallationStatus::CheckingForUpdate,
         );
 
-        let rid = match zed::current_platform() {
+        let rid = Self::rid();
+        self.nuget.apply_settings(&settings);
+        let package_id = Self::package_id(rid);
+        let mut source = PackageSource::Installed;
+        let version = match pinned_version {
+            Some(pinned) => pinned.to_string(),
+            None => match self
+                .nuget
+                .get_latest_version(&package_id, &settings.excluded_version_patterns)
+            {
+                Ok(version) => version,
+                // Offline: start the newest install that is still complete.
+                Err(e) if e.is_offline() => {
+                    let versions_dir = nuget::package_versions_dir(&package_id);
+                    let Some(install) = util::newest_local_install(&versions_dir, "", |dir| {
+                        Self::is_installed(rid, dir)
+                    }) else {
+                        return Err(e);
+                    };
+                    warn!("{e}; starting the installed Roslyn {}", install.version);
+                    source = PackageSource::OfflineFallback;
+                    install.version
+                }
+                Err(e) => return Err(e),
+            },
+        };
+        let version_dir = nuget::package_dir(&package_id, &version);
+
+        let already_installed = Self::is_installed(rid, &version_dir);
+
+        if !already_installed {
+            let _lock = util::InstallLock::acquire(Self::LANGUAGE_SERVER_ID).map_err(Error::Io)?;
+            // Another Zed instance may have installed it while we waited.
+            if !Self::is_installed(rid, &version_dir) {
+                zed::set_language_server_installation_status(
+                    language_server_id,
+                    &zed::LanguageServerInstallationStatus::Downloading,
+                );
+
+                if pinned_version.is_some() {
+                    self.nuget.ensure_version_exists(&package_id, &version)?;
+                }
+                self.install(&package_id, &version, rid, &version_dir)?;
+                source = PackageSource::Downloaded;
+
+                util::remove_outdated_package_versions(
+                    worktree,
+                    Self::LANGUAGE_SERVER_ID,
+                    &version_dir,
+                )
+                .map_err(Error::Io)?;
+            }
+        }
+
+        let server_path = Self::find_server_path(rid, &version_dir)?;
+        if let ServerPath::Exe(ref path) = server_path {
+            zed::make_file_executable(path)
+                .map_err(|e| Error::io(format!("failed to make '{path}' executable"), e))?;
+        }
+        if !already_installed {
+            // The apphost packages ship the server's .dll next to it.
+            let dll = match &server_path {
+                ServerPath::Exe(path) => {
+                    format!("{}.dll", path.strip_suffix(".exe").unwrap_or(path))
+                }
+                ServerPath::Dll(path) => path.clone(),
+            };
+            util::verify_reported_version(worktree, dotnet, "Roslyn", &dll, &version);
+        }
+
+        let installed_at = match source {
+            PackageSource::Downloaded => Some(installed::now()),
+            PackageSource::Installed | PackageSource::OfflineFallback => {
+                installed.installed_at(Self::LANGUAGE_SERVER_ID, &version)
+            }
+        };
+        info!(
+            "starting Roslyn {package_id} {version} from '{}'",
+            server_path.as_str()
+        );
+        let command = Self::build_command(worktree, dotnet, &server_path, binary_args)?;
+        self.cached_binary = Some(RoslynBinaryInfo {
+            server_path,
+            package_id,
+            version,
+            installed_at,
+            source,
+        });
+        Ok(command)
+    }
+
+    /// The runtime identifier of the package for this platform.
+    fn rid() -> &'static str {
+        Self::rid_for(zed::current_platform())
+    }
+
+    /// The runtime identifier of the package for `platform`. Platforms
+    /// without a native build, such as 32-bit x86, get the portable `any`
+    /// package.
+    fn rid_for(platform: (zed::Os, zed::Architecture)) -> &'static str {
+        match platform {
             (zed::Os::Windows, zed::Architecture::X8664) => "win-x64",
             (zed::Os::Windows, zed::Architecture::Aarch64) => "win-arm64",
             (zed::Os::Linux, zed::Architecture::X8664) => "linux-x64",
@@ -61,38 +228,103 @@ impl Roslyn {
             (zed::Os::Mac, zed::Architecture::X8664) => "osx-x64",
             (zed::Os::Mac, zed::Architecture::Aarch64) => "osx-arm64",
             _ => "any",
-        };
-
-        let package_id = format!("{PACKAGE_PREFIX}.{rid}");
-        let version = self.nuget.get_latest_version(&package_id)?;
-        let version_dir = format!("{}-{}", Self::LANGUAGE_SERVER_ID, version);
+        }
+    }
 
-        let already_installed = Self::find_server_path(rid, &version_dir)
-            .is_ok_and(|sp| fs::metadata(sp.as_str()).is_ok_and(|stat| stat.is_file()));
+    fn package_id(rid: &str) -> String {
+        format!("{PACKAGE_PREFIX}.{rid}")
+    }
 
-        if !already_installed {
-            zed::set_language_server_installation_status(
-                language_server_id,
-                &zed::LanguageServerInstallationStatus::Downloading,
-            );
+    /// The published versions of this platform's server package, newest
+    /// first, for choosing one to pin in `server_versions`.
+    pub fn available_versions(nuget: &mut NuGetClient) -> Result<Vec<String>> {
+        nuget.list_versions(&Self::package_id(Self::rid()))
+    }
 
-            self.nuget
-                .download_and_extract(&package_id, &version, &version_dir)?;
+    fn is_installed(rid: &str, version_dir: &str) -> bool {
+        Self::find_server_path(rid, version_dir)
+            .is_ok_and(|sp| fs::metadata(sp.as_str()).is_ok_and(|stat| stat.is_file()))
+    }
 
-            util::remove_outdated_versions(Self::LANGUAGE_SERVER_ID, &version_dir)?;
+    /// The server at `repo_server_path`, if that is set. It is not run before
+    /// Zed starts it, so opening a repository never executes its files unless
+    /// the user opted in.
+    fn repo_server(worktree: &zed::Worktree, settings: &CsharpSettings) -> Option<String> {
+        let relative = settings.repo_server_path.trim_matches('/');
+        if relative.is_empty() {
+            return None;
         }
+        let mut path = format!("{}/{relative}", worktree.root_path());
+        if zed::current_platform().0 == zed::Os::Windows && !path.ends_with(".exe") {
+            path.push_str(".exe");
+        }
+        Some(path)
+    }
 
-        let server_path = Self::find_server_path(rid, &version_dir)?;
-        if let ServerPath::Exe(ref path) = server_path {
-            zed::make_file_executable(path)?;
+    /// The install behind the last command this returned, if the extension
+    /// downloaded it. `None` when `binary.path` is set.
+    pub fn binary_info(&self) -> Option<&RoslynBinaryInfo> {
+        self.cached_binary.as_ref()
+    }
+
+    /// The version directory of the running install, which cache cleanup
+    /// must keep.
+    pub fn install_dir(&self) -> Option<String> {
+        self.cached_binary
+            .as_ref()
+            .map(|binary| nuget::package_dir(&binary.package_id, &binary.version))
+    }
+
+    /// Where `version` of this platform's package is installed.
+    pub fn version_dir(version: &str) -> String {
+        nuget::package_dir(&Self::package_id(Self::rid()), version)
+    }
+
+    /// Downloads into a temporary directory and only moves it to `version_dir`
+    /// once the server binary is found, so an interrupted or broken download
+    /// never replaces the current install.
+    fn install(
+        &mut self,
+        package_id: &str,
+        version: &str,
+        rid: &str,
+        version_dir: &str,
+    ) -> Result<()> {
+        let temp_dir = util::SimpleTempDir::new(&format!("{}-{version}", Self::LANGUAGE_SERVER_ID))
+            .map_err(Error::Io)?;
+        self.nuget
+            .download_and_extract(package_id, version, temp_dir.path())?;
+
+        let server_path = Self::find_server_path(rid, temp_dir.path())?;
+        if !fs::metadata(server_path.as_str()).is_ok_and(|stat| stat.is_file()) {
+            return Err(Error::Corrupted(format!(
+                "downloaded Roslyn package is missing '{}'",
+                server_path.as_str()
+            )));
         }
 
-        let command = Self::build_command(&server_path, binary_args);
-        self.cached_server_path = Some(server_path);
-        Ok(command)
+        fs::remove_dir_all(version_dir).ok();
+        temp_dir.persist(version_dir).map_err(Error::Io)?;
+        Ok(())
+    }
+
+    /// The `dotnet` that runs the portable (`any`) build of the server.
+    fn dotnet(worktree: &zed::Worktree, dotnet: &mut DotnetLocator) -> Result<String> {
+        dotnet.find(worktree).ok_or_else(|| {
+            Error::configuration(
+                "dotnet_path",
+                "the Roslyn server for this platform runs on .NET, but no dotnet was found; \
+                 install .NET or set `lsp.csharp.settings.dotnet_path`",
+            )
+        })
     }
 
-    fn build_command(server_path: &ServerPath, user_args: Option<Vec<String>>) -> zed::Command {
+    fn build_command(
+        worktree: &zed::Worktree,
+        dotnet: &mut DotnetLocator,
+        server_path: &ServerPath,
+        user_args: Option<Vec<String>>,
+    ) -> Result<zed::Command> {
         let mut extra_args = vec!["--stdio".to_string(), "--autoLoadProjects".to_string()];
         if let Some(args) = user_args {
             extra_args.extend(args);
@@ -102,17 +334,17 @@ impl Roslyn {
             ServerPath::Dll(path) => {
                 let mut args = vec!["exec".to_string(), path.clone()];
                 args.extend(extra_args);
-                zed::Command {
-                    command: "dotnet".to_string(),
+                Ok(zed::Command {
+                    command: Self::dotnet(worktree, dotnet)?,
                     args,
                     env: Default::default(),
-                }
+                })
             }
-            ServerPath::Exe(path) => zed::Command {
+            ServerPath::Exe(path) => Ok(zed::Command {
                 command: path.clone(),
                 args: extra_args,
                 env: Default::default(),
-            },
+            }),
         }
     }
 
@@ -120,7 +352,7 @@ impl Roslyn {
         let tools_dir = format!("{version_dir}/tools");
 
         let tfm = fs::read_dir(&tools_dir)
-            .map_err(|e| format!("failed to read tools directory '{tools_dir}': {e}"))?
+            .map_err(|e| Error::io(format!("failed to read tools directory '{tools_dir}'"), e))?
             .filter_map(|entry| {
                 let entry = entry.ok()?;
                 if entry.file_type().ok()?.is_dir() {
@@ -130,11 +362,15 @@ impl Roslyn {
                 }
             })
             .next()
-            .ok_or_else(|| format!("no TFM directory found inside '{tools_dir}'"))?;
+            .ok_or_else(|| {
+                Error::Corrupted(format!("no TFM directory found inside '{tools_dir}'"))
+            })?;
 
         let server_dir = format!("{tools_dir}/{tfm}/{rid}");
         match Self::server_path_for_rid(rid, server_dir) {
-            ServerPath::Dll(path) => Ok(ServerPath::Dll(util::absolute_path(&path)?)),
+            ServerPath::Dll(path) => Ok(ServerPath::Dll(
+                util::absolute_path(&path).map_err(Error::Io)?,
+            )),
             exe => Ok(exe),
         }
     }
@@ -179,24 +415,34 @@ impl Roslyn {
         });
 
         let config_map = roslyn_config.as_object_mut().unwrap();
+        let mut dropped_keys = Vec::new();
         if let zed::serde_json::Value::Object(settings_map) = settings {
             for (key, value) in settings_map {
-                if key.contains('|') {
+                match value {
                     // This is already in the language|category format
-                    if let zed::serde_json::Value::Object(nested_settings) = value {
+                    zed::serde_json::Value::Object(nested_settings) if key.contains('|') => {
                         for (nested_key, nested_value) in nested_settings {
                             // The key already contains the proper format, just add the setting
                             config_map.insert(format!("{key}.{nested_key}"), nested_value);
                         }
                     }
-                }
-                // Handle direct roslyn-format settings (fallback for any other format)
-                else if key.contains('.') {
-                    config_map.insert(key.clone(), value.clone());
+                    // Handle direct roslyn-format settings (fallback for any other format)
+                    value if key.contains('.') => {
+                        config_map.insert(key, value);
+                    }
+                    _ => dropped_keys.push(key),
                 }
             }
         }
 
+        if !dropped_keys.is_empty() {
+            warn!(
+                "ignoring Roslyn settings {}: expected `language|category` objects \
+                 (e.g. `\"csharp|inlay_hints\": {{ ... }}`) or `language|category.option` keys",
+                dropped_keys.join(", ")
+            );
+        }
+
         roslyn_config
     }
 }
@@ -213,3 +459,74 @@ impl ServerPath {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_package_of_each_platform() {
+        use zed::{Architecture, Os};
+        let cases = [
+            (Os::Windows, Architecture::X8664, "win-x64"),
+            (Os::Windows, Architecture::Aarch64, "win-arm64"),
+            (Os::Linux, Architecture::X8664, "linux-x64"),
+            (Os::Linux, Architecture::Aarch64, "linux-arm64"),
+            (Os::Mac, Architecture::X8664, "osx-x64"),
+            (Os::Mac, Architecture::Aarch64, "osx-arm64"),
+            (Os::Windows, Architecture::X86, "any"),
+            (Os::Linux, Architecture::X86, "any"),
+        ];
+        for (os, arch, expected) in cases {
+            assert_eq!(Roslyn::rid_for((os, arch)), expected);
+        }
+    }
+
+    #[test]
+    fn flattens_settings_into_roslyn_keys() {
+        // Dropped keys are logged.
+        util::tests::enter_scratch_dir();
+        let config = Roslyn::transform_settings_for_roslyn(zed::serde_json::json!({
+            "csharp|inlay_hints": {
+                "csharp_enable_inlay_hints_for_types": false,
+                "dotnet_enable_inlay_hints_for_parameters": false,
+            },
+            "csharp|code_lens.dotnet_enable_references_code_lens": true,
+            "csharp|background_analysis": "fullSolution",
+            "inlay_hints": { "enabled": true },
+            "log_level": "debug",
+        }));
+        let config = config.as_object().unwrap();
+
+        assert_eq!(
+            config["csharp|inlay_hints.csharp_enable_inlay_hints_for_types"],
+            false
+        );
+        assert_eq!(
+            config["csharp|inlay_hints.dotnet_enable_inlay_hints_for_parameters"],
+            false
+        );
+        assert_eq!(
+            config["csharp|code_lens.dotnet_enable_references_code_lens"],
+            true
+        );
+        // Defaults the user didn't override are kept.
+        assert_eq!(
+            config["csharp|code_lens.dotnet_enable_tests_code_lens"],
+            false
+        );
+        assert_eq!(
+            config["csharp|inlay_hints.csharp_enable_inlay_hints_for_collection_expressions"],
+            true
+        );
+
+        for dropped in ["csharp|background_analysis", "inlay_hints", "log_level"] {
+            assert!(
+                config.keys().all(|key| !key.starts_with(dropped)),
+                "{dropped}"
+            );
+        }
+        // Only the 12 defaults, three of them overridden.
+        assert_eq!(config.len(), 12);
+    }
+}
@@ -1,6 +1,19 @@
+use std::collections::hash_map::RandomState;
 use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Once;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use zed_extension_api::Result;
+use serde::{Deserialize, Serialize};
+use zed_extension_api::{self as zed, serde_json, Result};
+
+use crate::dotnet::{self, DotnetLocator};
+use crate::language_servers::nuget::NuGetVersion;
+use crate::settings::CsharpSettings;
 
 pub(super) fn absolute_path(path: &str) -> Result<String> {
     let cwd = std::env::current_dir()
@@ -8,18 +21,792 @@ pub(super) fn absolute_path(path: &str) -> Result<String> {
     Ok(cwd.join(path).to_string_lossy().into_owned())
 }
 
+/// Removes the installs in the working directory named `prefix` followed by
+/// a version, except `version_dir`. The prefix includes everything up to the
+/// version, so builds of one server installed side by side, like
+/// `omnisharp-1.39.12` and `omnisharp-framework-1.39.12`, don't remove each
+/// other.
 pub(super) fn remove_outdated_versions(
+    worktree: &zed::Worktree,
     language_server_id: &'static str,
+    prefix: &str,
     version_dir: &str,
 ) -> Result<()> {
-    let entries = fs::read_dir(".").map_err(|e| format!("failed to list working directory {e}"))?;
+    let settings = CsharpSettings::for_worktree(worktree);
+    if !settings.manage_installs {
+        return Ok(());
+    }
+    let preserve = preserved_files(&settings, language_server_id);
+
+    for install in local_installs(".", prefix) {
+        if install.path != version_dir {
+            remove_dir_preserving(Path::new(&install.path), Path::new(""), preserve);
+        }
+    }
+    Ok(())
+}
+
+/// Removes every version of the package in `package_dir`'s parent except
+/// `package_dir` itself, along with installs left in the working directory
+/// from before packages moved under `nuget/`.
+pub(super) fn remove_outdated_package_versions(
+    worktree: &zed::Worktree,
+    language_server_id: &'static str,
+    package_dir: &str,
+) -> Result<()> {
+    let settings = CsharpSettings::for_worktree(worktree);
+    if !settings.manage_installs {
+        return Ok(());
+    }
+    let preserve = preserved_files(&settings, language_server_id);
+
+    let package_dir = Path::new(package_dir);
+    if let Some(versions_dir) = package_dir.parent() {
+        let entries = fs::read_dir(versions_dir)
+            .map_err(|e| format!("failed to list directory '{}': {e}", versions_dir.display()))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("failed to load directory entry {e}"))?;
+            if entry.path() != package_dir {
+                remove_dir_preserving(&entry.path(), Path::new(""), preserve);
+            }
+        }
+    }
+
+    remove_outdated_versions(
+        worktree,
+        language_server_id,
+        &format!("{language_server_id}-"),
+        "",
+    )
+}
+
+fn preserved_files<'a>(settings: &'a CsharpSettings, language_server_id: &str) -> &'a [String] {
+    settings
+        .preserve_files
+        .get(language_server_id)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Removes `dir` except for the `preserve` paths, which are relative to the
+/// install directory. `relative` is `dir`'s path inside that directory.
+pub(crate) fn remove_dir_preserving(dir: &Path, relative: &Path, preserve: &[String]) {
+    let is_preserved = |path: &Path| preserve.iter().any(|kept| Path::new(kept) == path);
+    let contains_preserved = |path: &Path| {
+        preserve
+            .iter()
+            .any(|kept| Path::new(kept).starts_with(path) && Path::new(kept) != path)
+    };
+
+    if !contains_preserved(relative) {
+        fs::remove_dir_all(dir).ok();
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let entry_relative = relative.join(entry.file_name());
+        if is_preserved(&entry_relative) {
+            continue;
+        }
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            remove_dir_preserving(&entry.path(), &entry_relative, preserve);
+        } else {
+            fs::remove_file(entry.path()).ok();
+        }
+    }
+}
+
+/// Runs `dotnet exec <dll> --version` in the worktree's shell environment and
+/// warns when the version it prints doesn't match `expected`. Only runs when
+/// `verify_downloads` is enabled and never fails.
+///
+/// Servers are checked through their `.dll` rather than their apphost so that
+/// the extension only ever needs to run `dotnet exec`, never an arbitrary
+/// executable.
+pub(super) fn verify_reported_version(
+    worktree: &zed::Worktree,
+    dotnet: &mut DotnetLocator,
+    server_name: &str,
+    dll: &str,
+    expected: &str,
+) {
+    if !CsharpSettings::for_worktree(worktree).verify_downloads {
+        return;
+    }
+    let Some(dotnet) = dotnet.find(worktree) else {
+        warn!("can't verify the {server_name} version: no dotnet found");
+        return;
+    };
+    let dll = match absolute_path(dll) {
+        Ok(dll) => dll,
+        Err(e) => {
+            warn!("failed to verify the {server_name} version: {e}");
+            return;
+        }
+    };
+
+    let output = match dotnet::command(worktree, &dotnet)
+        .args(["exec", dll.as_str(), "--version"])
+        .output()
+    {
+        Ok(output) if output.status == Some(0) => output,
+        Ok(output) => {
+            warn!(
+                "failed to verify the {server_name} version: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return;
+        }
+        Err(e) => {
+            warn!("failed to verify the {server_name} version: {e}");
+            return;
+        }
+    };
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let reported = stdout
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .and_then(|line| line.split_whitespace().last())
+        .unwrap_or_default();
+    if normalize_version(reported) != normalize_version(expected) {
+        warn!(
+            "{server_name} reports version '{reported}' but '{expected}' was downloaded; \
+             the release may be mislabeled"
+        );
+    }
+}
+
+/// Strips a leading `v` and any `+build` metadata, so `v1.2.3` and
+/// `1.2.3+abc123` compare equal. The comparison ignores ASCII case.
+fn normalize_version(version: &str) -> String {
+    let version = version.trim();
+    let version = version.strip_prefix(['v', 'V']).unwrap_or(version);
+    let version = version
+        .split_once('+')
+        .map_or(version, |(version, _)| version);
+    version.to_ascii_lowercase()
+}
+
+/// Holds every `SimpleTempDir`, keeping partial downloads away from the
+/// installs in the working directory.
+pub(crate) const TEMP_DIR: &str = "tmp";
+
+/// Temp directories older than this are left over from an install that
+/// never finished, e.g. because Zed was killed mid-download. No download
+/// takes this long, so a live install's directory is never swept.
+const STALE_TEMP_DIR_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A directory under `tmp/` in the extension's working directory that is
+/// removed on drop.
+pub(super) struct SimpleTempDir {
+    path: PathBuf,
+}
+
+impl SimpleTempDir {
+    /// Creates `tmp/<prefix>_<nanos>-<counter>-<random>`. The first call also
+    /// sweeps temp directories left behind by earlier sessions.
+    ///
+    /// The directory must not exist yet, so two installs can never share one;
+    /// on a collision another name is tried.
+    pub(super) fn new(prefix: &str) -> Result<Self> {
+        const ATTEMPTS: u32 = 8;
+        static SWEEP: Once = Once::new();
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        SWEEP.call_once(remove_stale_temp_dirs);
+        fs::create_dir_all(TEMP_DIR)
+            .map_err(|e| format!("failed to create temp directory '{TEMP_DIR}': {e}"))?;
+
+        let mut last_error = None;
+        for _ in 0..ATTEMPTS {
+            let counter = COUNTER.fetch_add(1, Ordering::Relaxed);
+            let random = RandomState::new().build_hasher().finish();
+            let path = Path::new(TEMP_DIR)
+                .join(format!("{prefix}_{}-{counter}-{random:016x}", now_nanos()));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => last_error = Some(e),
+                Err(e) => {
+                    return Err(format!(
+                        "failed to create temp directory '{}': {e}",
+                        path.display()
+                    ))
+                }
+            }
+        }
+        Err(format!(
+            "failed to create a unique temp directory for '{prefix}': {}",
+            last_error.map(|e| e.to_string()).unwrap_or_default()
+        ))
+    }
+
+    pub(super) fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+
+    /// Returns the path and leaves the directory in place; the caller owns
+    /// it from then on.
+    pub(super) fn into_path(mut self) -> PathBuf {
+        mem::take(&mut self.path)
+    }
+
+    /// Moves the directory to `dest`, replacing nothing: `dest` must not
+    /// exist. On failure the temp directory is removed.
+    pub(super) fn persist(self, dest: &str) -> Result<PathBuf> {
+        let path = self.into_path();
+        let from = path.to_str().unwrap_or_default();
+        move_dir(from, dest).inspect_err(|_| {
+            fs::remove_dir_all(&path).ok();
+        })?;
+        Ok(PathBuf::from(dest))
+    }
+}
+
+impl Drop for SimpleTempDir {
+    fn drop(&mut self) {
+        // Empty after `into_path`.
+        if !self.path.as_os_str().is_empty() {
+            fs::remove_dir_all(&self.path).ok();
+        }
+    }
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_nanos())
+        .unwrap_or_default()
+}
+
+/// Removes directories in `tmp/` whose creation timestamp is older than
+/// `STALE_TEMP_DIR_AGE`; directories without a readable timestamp are kept.
+fn remove_stale_temp_dirs() {
+    let now = now_nanos();
+    let stale = fs::read_dir(TEMP_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.rsplit_once('_'))
+                .and_then(|(_, suffix)| suffix.split('-').next()?.parse::<u128>().ok())
+                .is_some_and(|created| now.saturating_sub(created) >= STALE_TEMP_DIR_AGE.as_nanos())
+        });
+
+    for entry in stale {
+        let path = entry.path();
+        match fs::remove_dir_all(&path) {
+            Ok(()) => info!("removed stale temp directory '{}'", path.display()),
+            Err(e) => warn!(
+                "failed to remove stale temp directory '{}': {e}",
+                path.display()
+            ),
+        }
+    }
+}
+
+/// Moves `from` to `to`, copying and deleting when a rename isn't possible.
+fn move_dir(from: &str, to: &str) -> Result<()> {
+    if let Some(parent) = Path::new(to).parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("failed to create directory '{}': {e}", parent.display()))?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_dir_all(Path::new(from), Path::new(to)).inspect_err(|_| {
+        fs::remove_dir_all(to).ok();
+    })?;
+    fs::remove_dir_all(from).ok();
+    Ok(())
+}
+
+fn copy_dir_all(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)
+        .map_err(|e| format!("failed to create directory '{}': {e}", to.display()))?;
+    let entries = fs::read_dir(from)
+        .map_err(|e| format!("failed to list directory '{}': {e}", from.display()))?;
     for entry in entries {
         let entry = entry.map_err(|e| format!("failed to load directory entry {e}"))?;
-        if entry.file_name().to_str().is_none_or(|file_name| {
-            file_name.starts_with(language_server_id) && file_name != version_dir
-        }) {
-            fs::remove_dir_all(entry.path()).ok();
+        let target = to.join(entry.file_name());
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target).map_err(|e| {
+                format!(
+                    "failed to copy '{}' to '{}': {e}",
+                    entry.path().display(),
+                    target.display()
+                )
+            })?;
         }
     }
     Ok(())
 }
+
+/// Holds one `<owner>-<repo>.json` file per repository with the latest
+/// release seen, so most launches don't query the GitHub API.
+pub(crate) const RELEASE_CACHE_DIR: &str = "releases";
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Clone, Debug, PartialEq))]
+pub(super) struct CachedRelease {
+    pub version: String,
+    pub assets: Vec<CachedAsset>,
+    /// Seconds since the Unix epoch.
+    fetched_at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(test, derive(Clone, Debug, PartialEq))]
+pub(super) struct CachedAsset {
+    pub name: String,
+    pub download_url: String,
+}
+
+/// Returns the latest release of `repo`, from the cache when it was fetched
+/// less than `ttl` ago. A `ttl` of zero always refreshes.
+///
+/// When the fetch fails, e.g. because the API is rate limited, an expired
+/// cache entry is used instead. A cache file that doesn't parse is ignored
+/// and overwritten by the next successful fetch.
+pub(super) fn cached_latest_release(
+    repo: &str,
+    options: zed::GithubReleaseOptions,
+    ttl: Duration,
+) -> Result<CachedRelease> {
+    let cache_path = format!("{RELEASE_CACHE_DIR}/{}.json", repo.replace('/', "-"));
+    let now = (now_nanos() / 1_000_000_000) as u64;
+    let cached = fs::read(&cache_path)
+        .ok()
+        .and_then(|content| serde_json::from_slice::<CachedRelease>(&content).ok());
+
+    let (fetched, is_new) = cached_or_fetched(repo, cached, now, ttl, || {
+        let release = zed::latest_github_release(repo, options)?;
+        Ok(CachedRelease {
+            version: release.version,
+            assets: release
+                .assets
+                .into_iter()
+                .map(|asset| CachedAsset {
+                    name: asset.name,
+                    download_url: asset.download_url,
+                })
+                .collect(),
+            fetched_at: now,
+        })
+    })?;
+    if !is_new {
+        return Ok(fetched);
+    }
+    let saved = fs::create_dir_all(RELEASE_CACHE_DIR)
+        .map_err(|e| e.to_string())
+        .and_then(|()| serde_json::to_vec(&fetched).map_err(|e| e.to_string()))
+        .and_then(|content| fs::write(&cache_path, content).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        warn!("failed to cache the {repo} release in '{cache_path}': {e}");
+    }
+    Ok(fetched)
+}
+
+/// Takes the release from `cached` while it was fetched less than `ttl`
+/// before `now`, in seconds since the Unix epoch, and from `fetch`
+/// otherwise. An expired `cached` is still used when `fetch` fails. Also
+/// returns whether the release is new and should be cached.
+fn cached_or_fetched(
+    repo: &str,
+    cached: Option<CachedRelease>,
+    now: u64,
+    ttl: Duration,
+    fetch: impl FnOnce() -> Result<CachedRelease>,
+) -> Result<(CachedRelease, bool)> {
+    let cached = match cached {
+        Some(cached) if Duration::from_secs(now.saturating_sub(cached.fetched_at)) < ttl => {
+            return Ok((cached, false))
+        }
+        cached => cached,
+    };
+    match fetch() {
+        Ok(fetched) => Ok((fetched, true)),
+        Err(e) => {
+            let Some(cached) = cached else {
+                return Err(e);
+            };
+            warn!("{e}; using the cached {repo} release {}", cached.version);
+            Ok((cached, false))
+        }
+    }
+}
+
+/// A version directory found on disk.
+pub(super) struct LocalInstall {
+    pub version: String,
+    pub path: String,
+}
+
+/// The entries of `dir` named `prefix` followed by a version, newest first.
+/// Versions compare numerically, so `0.9.10` sorts above `0.9.9`, and a
+/// prerelease sorts below its release. Entries whose suffix isn't a version
+/// are skipped.
+pub(super) fn local_installs(dir: &str, prefix: &str) -> Vec<LocalInstall> {
+    let mut installs: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let version = NuGetVersion::parse(name.strip_prefix(prefix)?)?;
+            let path = if dir == "." {
+                name
+            } else {
+                format!("{dir}/{name}")
+            };
+            Some((version, path))
+        })
+        .collect();
+    installs.sort_by(|(a, _), (b, _)| b.cmp(a));
+    installs
+        .into_iter()
+        .map(|(version, path)| LocalInstall {
+            version: version.raw,
+            path,
+        })
+        .collect()
+}
+
+/// The newest of `local_installs` for which `is_valid` holds, e.g. because
+/// its server binary exists. Used to start offline when the latest version
+/// can't be looked up.
+pub(super) fn newest_local_install(
+    dir: &str,
+    prefix: &str,
+    is_valid: impl Fn(&str) -> bool,
+) -> Option<LocalInstall> {
+    local_installs(dir, prefix)
+        .into_iter()
+        .find(|install| is_valid(&install.path))
+}
+
+/// Whether a server's cached install of `installed_version` may be reused:
+/// always when no version is pinned, otherwise only when it is the pinned
+/// one, so changing the pin installs the version it names.
+pub(super) fn reuses_cached_install(pinned: Option<&str>, installed_version: Option<&str>) -> bool {
+    pinned.is_none_or(|pinned| {
+        installed_version
+            .is_some_and(|installed| normalize_version(installed) == normalize_version(pinned))
+    })
+}
+
+/// Holds the `InstallLock` files.
+const LOCK_DIR: &str = "locks";
+
+/// How long `InstallLock::acquire` waits for another install to finish.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// A lock older than this is left over from an install that never finished
+/// and is broken. Extensions can't see other processes, so a lock's age is
+/// the only liveness check available.
+const STALE_LOCK_AGE: Duration = Duration::from_secs(15 * 60);
+
+/// Serializes installs of one component across Zed instances sharing the
+/// working directory. Held as `locks/<name>.lock`, removed on drop.
+pub(super) struct InstallLock {
+    path: PathBuf,
+    owner: String,
+}
+
+impl InstallLock {
+    /// Takes the lock, waiting up to `LOCK_TIMEOUT` while another install
+    /// holds it. The file records who took it and when, for diagnosing a
+    /// lock that is never released.
+    pub(super) fn acquire(name: &str) -> Result<Self> {
+        Self::acquire_within(name, LOCK_TIMEOUT)
+    }
+
+    fn acquire_within(name: &str, timeout: Duration) -> Result<Self> {
+        fs::create_dir_all(LOCK_DIR)
+            .map_err(|e| format!("failed to create lock directory '{LOCK_DIR}': {e}"))?;
+        let path = Path::new(LOCK_DIR).join(format!("{name}.lock"));
+        let owner = format!(
+            "{:016x} {}",
+            RandomState::new().build_hasher().finish(),
+            now_nanos() / 1_000_000_000
+        );
+
+        let started = SystemTime::now();
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(mut file) => {
+                    std::io::Write::write_all(&mut file, owner.as_bytes()).ok();
+                    return Ok(Self { path, owner });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+                Err(e) => {
+                    return Err(format!(
+                        "failed to create lock file '{}': {e}",
+                        path.display()
+                    ))
+                }
+            }
+
+            if let Some(stale_owner) = fs::read_to_string(&path)
+                .ok()
+                .filter(|_| lock_age(&path).is_some_and(|age| age > STALE_LOCK_AGE))
+            {
+                if break_stale_lock(&path, &stale_owner) {
+                    warn!("broke stale install lock '{}'", path.display());
+                }
+                continue;
+            }
+            if started.elapsed().unwrap_or_default() >= timeout {
+                return Err(format!(
+                    "timed out waiting for another install of {name} to finish; \
+                     delete '{}' if no install is running",
+                    path.display()
+                ));
+            }
+            std::thread::sleep(Duration::from_millis(500));
+        }
+    }
+}
+
+impl Drop for InstallLock {
+    fn drop(&mut self) {
+        // The lock may have been broken as stale and taken by someone else.
+        if fs::read_to_string(&self.path).is_ok_and(|owner| owner == self.owner) {
+            fs::remove_file(&self.path).ok();
+        }
+    }
+}
+
+/// Removes the lock at `path` if it still belongs to `stale_owner`. The lock
+/// is first renamed to a unique name, which only one of several installs
+/// breaking it at once can do, and then checked, so a lock that was broken
+/// and taken again in the meantime is put back rather than removed.
+fn break_stale_lock(path: &Path, stale_owner: &str) -> bool {
+    let broken = path.with_extension(format!(
+        "lock.broken-{:016x}",
+        RandomState::new().build_hasher().finish()
+    ));
+    if fs::rename(path, &broken).is_err() {
+        // Another install broke it first.
+        return false;
+    }
+    if fs::read_to_string(&broken).is_ok_and(|owner| owner == stale_owner) {
+        fs::remove_file(&broken).ok();
+        return true;
+    }
+    // Linking fails rather than replacing a lock taken since.
+    fs::hard_link(&broken, path).ok();
+    fs::remove_file(&broken).ok();
+    false
+}
+
+/// Whether an install currently holds a lock that isn't stale.
+pub(crate) fn install_in_progress() -> bool {
+    fs::read_dir(LOCK_DIR)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "lock")
+        })
+        .any(|path| lock_age(&path).is_some_and(|age| age <= STALE_LOCK_AGE))
+}
+
+/// How long ago the lock at `path` was taken, from the timestamp written into
+/// it or, while it is still being written, its modification time.
+fn lock_age(path: &Path) -> Option<Duration> {
+    let taken_at = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| content.split_whitespace().nth(1)?.parse::<u64>().ok())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+        .or_else(|| fs::metadata(path).ok()?.modified().ok())?;
+    SystemTime::now().duration_since(taken_at).ok()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Moves the test process into a fresh directory standing in for the
+    /// extension's working directory, once for all tests.
+    pub(crate) fn enter_scratch_dir() {
+        static ENTER: Once = Once::new();
+        ENTER.call_once(|| {
+            let dir = std::env::temp_dir().join(format!("zed-csharp-tests-{}", now_nanos()));
+            fs::create_dir_all(&dir).unwrap();
+            std::env::set_current_dir(&dir).unwrap();
+        });
+    }
+
+    #[test]
+    fn normalizes_versions() {
+        assert_eq!(normalize_version(" v1.39.12\n"), "1.39.12");
+        assert_eq!(normalize_version("5.0.0-RC.1+abc123"), "5.0.0-rc.1");
+        assert_eq!(normalize_version("V2"), "2");
+    }
+
+    #[test]
+    fn install_locks_are_exclusive_until_dropped() {
+        enter_scratch_dir();
+        let lock = InstallLock::acquire("lock-test").unwrap();
+        let path = Path::new(LOCK_DIR).join("lock-test.lock");
+        assert!(path.is_file());
+        assert!(install_in_progress());
+
+        let contended = InstallLock::acquire_within("lock-test", Duration::ZERO);
+        assert!(contended
+            .err()
+            .is_some_and(|e| e.starts_with("timed out waiting for another install of lock-test")));
+
+        drop(lock);
+        assert!(!path.exists());
+        drop(InstallLock::acquire_within("lock-test", Duration::ZERO).unwrap());
+    }
+
+    #[test]
+    fn stale_install_locks_are_broken() {
+        enter_scratch_dir();
+        fs::create_dir_all(LOCK_DIR).unwrap();
+        let path = Path::new(LOCK_DIR).join("stale-lock-test.lock");
+        // Taken at the Unix epoch by an install that never finished.
+        fs::write(&path, "0000000000000000 0").unwrap();
+
+        let lock = InstallLock::acquire_within("stale-lock-test", Duration::ZERO).unwrap();
+        assert_ne!(fs::read_to_string(&path).unwrap(), "0000000000000000 0");
+
+        // A lock taken again since it was found stale is put back.
+        assert!(!break_stale_lock(&path, "0000000000000000 0"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), lock.owner);
+
+        // Dropping a lock that was broken and taken by someone else keeps it.
+        fs::write(&path, "ffffffffffffffff 0").unwrap();
+        drop(lock);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ffffffffffffffff 0");
+        assert!(break_stale_lock(&path, "ffffffffffffffff 0"));
+        assert!(!path.exists());
+    }
+
+    fn release(version: &str, fetched_at: u64) -> CachedRelease {
+        CachedRelease {
+            version: version.to_string(),
+            assets: Vec::new(),
+            fetched_at,
+        }
+    }
+
+    #[test]
+    fn uses_the_cached_release_until_it_expires() {
+        let ttl = Duration::from_secs(60);
+        let lookup = |cached, now, ttl| {
+            cached_or_fetched("OmniSharp/omnisharp-roslyn", cached, now, ttl, || {
+                Ok(release("v1.39.13", now))
+            })
+        };
+
+        let hit = lookup(Some(release("v1.39.12", 1_000)), 1_059, ttl);
+        assert_eq!(hit, Ok((release("v1.39.12", 1_000), false)));
+
+        let expired = lookup(Some(release("v1.39.12", 1_000)), 1_060, ttl);
+        assert_eq!(expired, Ok((release("v1.39.13", 1_060), true)));
+
+        let uncached = lookup(None, 1_000, ttl);
+        assert_eq!(uncached, Ok((release("v1.39.13", 1_000), true)));
+
+        let refresh = lookup(Some(release("v1.39.12", 1_000)), 1_000, Duration::ZERO);
+        assert_eq!(refresh, Ok((release("v1.39.13", 1_000), true)));
+    }
+
+    #[test]
+    fn falls_back_to_an_expired_release_when_rate_limited() {
+        enter_scratch_dir();
+        let rate_limited = || Err("API rate limit exceeded".to_string());
+        let ttl = Duration::from_secs(60);
+
+        let fallback = cached_or_fetched(
+            "OmniSharp/omnisharp-roslyn",
+            Some(release("v1.39.12", 0)),
+            1_000,
+            ttl,
+            rate_limited,
+        );
+        assert_eq!(fallback, Ok((release("v1.39.12", 0), false)));
+
+        let failed =
+            cached_or_fetched("OmniSharp/omnisharp-roslyn", None, 1_000, ttl, rate_limited);
+        assert_eq!(failed, Err("API rate limit exceeded".to_string()));
+    }
+
+    #[test]
+    fn reuses_cached_installs_of_the_pinned_version() {
+        assert!(reuses_cached_install(None, Some("5.0.0-1.25277.114")));
+        assert!(reuses_cached_install(None, None));
+        assert!(reuses_cached_install(
+            Some("5.0.0-1.25277.114"),
+            Some("5.0.0-1.25277.114")
+        ));
+        assert!(reuses_cached_install(
+            Some("0.20.0-RC.1"),
+            Some("0.20.0-rc.1")
+        ));
+        assert!(!reuses_cached_install(
+            Some("4.14.0-3.25168.13"),
+            Some("5.0.0-1.25277.114")
+        ));
+        assert!(!reuses_cached_install(Some("0.20.0"), None));
+    }
+
+    #[test]
+    fn removes_everything_but_preserved_files() {
+        enter_scratch_dir();
+        let dir = Path::new("preserve-test");
+        fs::create_dir_all(dir.join("config")).unwrap();
+        fs::create_dir_all(dir.join("bin")).unwrap();
+        fs::write(dir.join("config/omnisharp.json"), "{}").unwrap();
+        fs::write(dir.join("config/other.json"), "{}").unwrap();
+        fs::write(dir.join("bin/OmniSharp.dll"), "").unwrap();
+
+        remove_dir_preserving(dir, Path::new(""), &["config/omnisharp.json".to_string()]);
+        assert!(dir.join("config/omnisharp.json").is_file());
+        assert!(!dir.join("config/other.json").exists());
+        assert!(!dir.join("bin").exists());
+
+        remove_dir_preserving(dir, Path::new(""), &[]);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn temp_dirs_are_unique_and_removed_on_drop() {
+        enter_scratch_dir();
+        let first = SimpleTempDir::new("roslyn-5.0.0").unwrap();
+        let second = SimpleTempDir::new("roslyn-5.0.0").unwrap();
+        assert_ne!(first.path(), second.path());
+        assert!(first.path().starts_with(TEMP_DIR));
+
+        let path = first.path().to_string();
+        drop(first);
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn persisted_temp_dirs_move_into_place() {
+        enter_scratch_dir();
+        let temp_dir = SimpleTempDir::new("csharp-ls-0.20.0").unwrap();
+        fs::write(Path::new(temp_dir.path()).join("server.dll"), "").unwrap();
+        let temp_path = temp_dir.path().to_string();
+
+        let persisted = temp_dir.persist("persist-test/csharp-ls-0.20.0").unwrap();
+        assert!(persisted.join("server.dll").is_file());
+        assert!(!Path::new(&temp_path).exists());
+        fs::remove_dir_all("persist-test").unwrap();
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+use crate::installed;
+
+/// Kept in the extension's working directory, next to the installs.
+pub const LOG_FILE: &str = "csharp-extension.log";
+
+/// The previous log, replaced on each rotation.
+const ROTATED_LOG_FILE: &str = "csharp-extension.log.1";
+
+/// The log is rotated once it grows past this, so the two files together
+/// stay under 4 MB.
+const MAX_LOG_SIZE: u64 = 2 * 1024 * 1024;
+
+/// How much the extension writes to `csharp-extension.log`. Warnings and
+/// errors also go to Zed's log regardless of the level.
+#[derive(Clone, Copy, PartialEq, PartialOrd, Default, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Sets the level for every later message. Settings are per worktree but the
+/// log is shared, so the most recently started worktree's level applies.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+fn enabled(level: LogLevel) -> bool {
+    level != LogLevel::Off && level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// The log's absolute path, for pointing users at it.
+pub fn log_path() -> String {
+    std::env::current_dir()
+        .map(|dir| dir.join(LOG_FILE).to_string_lossy().into_owned())
+        .unwrap_or_else(|_| LOG_FILE.to_string())
+}
+
+/// Use the `error!`, `warn!`, `info!` and `debug!` macros instead.
+pub fn write(level: LogLevel, message: fmt::Arguments<'_>) {
+    if matches!(level, LogLevel::Error | LogLevel::Warn) {
+        eprintln!("{message}");
+    }
+    if !enabled(level) {
+        return;
+    }
+
+    if fs::metadata(LOG_FILE).is_ok_and(|stat| stat.len() > MAX_LOG_SIZE) {
+        fs::rename(LOG_FILE, ROTATED_LOG_FILE).ok();
+    }
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let seconds_of_day = timestamp % 86_400;
+    let line = format!(
+        "{} {:02}:{:02}:{:02} {:<5} {message}\n",
+        installed::format_date(timestamp),
+        seconds_of_day / 3_600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        format!("{level:?}").to_uppercase(),
+    );
+    // Logging must never be the reason something fails.
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(LOG_FILE) {
+        file.write_all(line.as_bytes()).ok();
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::LogLevel::Error, format_args!($($arg)*))
+    };
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::LogLevel::Warn, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::LogLevel::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::logging::write($crate::logging::LogLevel::Debug, format_args!($($arg)*))
+    };
+}
@@ -0,0 +1,200 @@
+use zed_extension_api as zed;
+
+use crate::dotnet;
+use crate::settings::CsharpSettings;
+
+/// Folders Unity regenerates on every import. Loading them only slows down
+/// project discovery.
+pub const UNITY_EXCLUDE_PATTERNS: &[&str] = &["Library/**", "Temp/**"];
+
+pub struct UnityProject {
+    /// The editor version from `ProjectVersion.txt`, e.g. `2022.3.10f1`.
+    pub editor_version: Option<String>,
+}
+
+/// Detects a Unity project from `ProjectSettings/ProjectVersion.txt` at the
+/// worktree root, unless `unity` is turned off in the extension settings.
+///
+/// Extensions can't check for directories, so `Assets/` isn't required.
+pub fn find_unity_project(worktree: &zed::Worktree) -> Option<UnityProject> {
+    if !CsharpSettings::for_worktree(worktree).unity {
+        return None;
+    }
+    let content = worktree
+        .read_text_file("ProjectSettings/ProjectVersion.txt")
+        .ok()?;
+    let editor_version = content.lines().find_map(|line| {
+        let version = line.trim().strip_prefix("m_EditorVersion:")?.trim();
+        (!version.is_empty()).then(|| version.to_string())
+    });
+    Some(UnityProject { editor_version })
+}
+
+pub struct GodotProject {
+    /// The engine version from `config/features`, e.g. `4.2`.
+    pub version: Option<String>,
+}
+
+impl GodotProject {
+    /// The `GodotTargetPlatform` the Godot .NET SDK expects for design-time
+    /// builds on this machine.
+    pub fn target_platform() -> &'static str {
+        match zed::current_platform().0 {
+            zed::Os::Mac => "macos",
+            zed::Os::Linux => "linuxbsd",
+            zed::Os::Windows => "windows",
+        }
+    }
+
+    /// `GODOT_BIN` for the Godot SDK, when a `godot` executable is on the
+    /// worktree's PATH.
+    pub fn env(worktree: &zed::Worktree) -> zed::EnvVars {
+        worktree
+            .which("godot")
+            .map(|path| vec![("GODOT_BIN".to_string(), path)])
+            .unwrap_or_default()
+    }
+}
+
+/// Detects a Godot project from `project.godot` at the worktree root.
+pub fn find_godot_project(worktree: &zed::Worktree) -> Option<GodotProject> {
+    let content = worktree.read_text_file("project.godot").ok()?;
+    // config/features=PackedStringArray("4.2", "C#", "Forward Plus")
+    let version = content.lines().find_map(|line| {
+        let features = line.trim().strip_prefix("config/features=")?;
+        features
+            .split('"')
+            .skip(1)
+            .step_by(2)
+            .find(|feature| feature.starts_with(|c: char| c.is_ascii_digit()))
+            .map(str::to_string)
+    });
+    Some(GodotProject { version })
+}
+
+/// The namespace for a type in `dir` (relative to the worktree root): the
+/// containing project's `RootNamespace`, or its name, followed by the folders
+/// below the project.
+///
+/// Extensions can't list directories, so a folder only counts as a project
+/// when it contains a project file named after it, e.g. `src/App/App.csproj`.
+pub fn namespace_for(worktree: &zed::Worktree, dir: &str) -> Option<String> {
+    let root_path = worktree.root_path();
+    let root_name = root_path
+        .rsplit(['/', '\\'])
+        .find(|name| !name.is_empty())
+        .unwrap_or_default();
+    namespace_in(root_name, dir, |path| worktree.read_text_file(path).ok())
+}
+
+/// `namespace_for` with the worktree root's folder name and a way to read
+/// files relative to the root.
+fn namespace_in(
+    root_name: &str,
+    dir: &str,
+    read_text_file: impl Fn(&str) -> Option<String>,
+) -> Option<String> {
+    let folders: Vec<&str> = dir
+        .split(['/', '\\'])
+        .filter(|folder| !folder.is_empty() && *folder != ".")
+        .collect();
+
+    (0..=folders.len()).rev().find_map(|depth| {
+        let project_name = folders[..depth].last().copied().unwrap_or(root_name);
+        let project_file = folders[..depth]
+            .iter()
+            .copied()
+            .chain([format!("{project_name}.csproj").as_str()])
+            .collect::<Vec<_>>()
+            .join("/");
+        let content = read_text_file(&project_file)?;
+        let root_namespace = dotnet::xml_element_text(&content, "RootNamespace")
+            .filter(|root_namespace| !root_namespace.is_empty())
+            .unwrap_or(project_name);
+        Some(
+            root_namespace
+                .split('.')
+                .chain(folders[depth..].iter().copied())
+                .map(namespace_identifier)
+                .collect::<Vec<_>>()
+                .join("."),
+        )
+    })
+}
+
+/// Replaces characters that can't appear in an identifier with `_`, the way
+/// MSBuild derives `RootNamespace` from a project name.
+fn namespace_identifier(name: &str) -> String {
+    let identifier: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if identifier.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{identifier}")
+    } else {
+        identifier
+    }
+}
+
+/// Whether the root `.editorconfig` asks for block-scoped namespaces.
+/// File-scoped namespaces are the default.
+pub fn prefers_block_scoped_namespaces(worktree: &zed::Worktree) -> bool {
+    let Ok(content) = worktree.read_text_file(".editorconfig") else {
+        return false;
+    };
+    content.lines().any(|line| {
+        line.split_once('=').is_some_and(|(key, value)| {
+            key.trim() == "csharp_style_namespace_declarations"
+                && value.trim().starts_with("block_scoped")
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files<'a>(files: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |path| {
+            files
+                .iter()
+                .find(|(name, _)| *name == path)
+                .map(|(_, content)| content.to_string())
+        }
+    }
+
+    #[test]
+    fn namespace_follows_folders_below_the_project() {
+        let read = files(&[("src/App/App.csproj", "<Project></Project>")]);
+        assert_eq!(
+            namespace_in("repo", "src/App/Models/View Models", &read).as_deref(),
+            Some("App.Models.View_Models")
+        );
+        assert_eq!(
+            namespace_in("repo", "src/App", &read).as_deref(),
+            Some("App")
+        );
+        assert_eq!(namespace_in("repo", "src/Other", &read), None);
+    }
+
+    #[test]
+    fn namespace_uses_root_namespace_and_root_project() {
+        let read = files(&[(
+            "My-App.csproj",
+            "<Project><PropertyGroup><RootNamespace>Company.Product</RootNamespace></PropertyGroup></Project>",
+        )]);
+        assert_eq!(
+            namespace_in("My-App", "./1st", &read).as_deref(),
+            Some("Company.Product._1st")
+        );
+
+        let read = files(&[("My-App.csproj", "<Project></Project>")]);
+        assert_eq!(namespace_in("My-App", "", &read).as_deref(), Some("My_App"));
+    }
+}
@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use zed_extension_api::{self as zed, serde_json, settings::LspSettings};
+
+use crate::logging::LogLevel;
+
+/// The settings key for options that apply to the extension rather than to a
+/// particular language server (`lsp.csharp.settings`).
+pub const EXTENSION_SETTINGS_KEY: &str = "csharp";
+
+/// Extension-wide settings from `lsp.csharp.settings`.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct CsharpSettings {
+    /// Whether any C# language server should start in this worktree. On by
+    /// default.
+    pub enabled: bool,
+    /// Path to the `dotnet` executable. Defaults to `dotnet` on the worktree's
+    /// PATH.
+    pub dotnet_path: Option<String>,
+    /// Run `dotnet workload list` before starting a server and warn about
+    /// missing `required_workloads`. Off by default.
+    pub check_workloads: bool,
+    pub required_workloads: Vec<String>,
+    /// Whether the extension may delete outdated installs. On by default.
+    pub manage_installs: bool,
+    /// Extra headers for the extension's NuGet requests. These only apply to
+    /// requests the extension makes itself; package downloads go through Zed's
+    /// `download_file`, which doesn't accept headers.
+    pub http_headers: BTreeMap<String, String>,
+    /// Substrings that exclude a NuGet version from automatic updates, e.g.
+    /// `["rc", "beta"]` for feeds that publish release candidates as regular
+    /// versions. Empty by default.
+    pub excluded_version_patterns: Vec<String>,
+    /// Apply the Unity profile when `ProjectSettings/ProjectVersion.txt`
+    /// exists. On by default.
+    pub unity: bool,
+    /// Run a freshly downloaded server with `dotnet exec <dll> --version` and
+    /// warn when it doesn't report the version that was downloaded. Builds
+    /// that don't run on `dotnet` aren't checked. Off by default.
+    pub verify_downloads: bool,
+    /// Whether the extension may download servers and packages.
+    pub auto_download: AutoDownload,
+    /// Paths inside an install, by language server id, that cleanup of old
+    /// versions must keep, e.g. `{"roslyn": ["tools/net9.0/any/log.config"]}`.
+    pub preserve_files: BTreeMap<String, Vec<String>>,
+    /// How the extension's own HTTP requests follow redirects.
+    pub redirects: Redirects,
+    /// Exact package versions to install instead of the latest, by language
+    /// server id, e.g. `{"roslyn": "5.0.0-1.25277.114"}`.
+    pub server_versions: BTreeMap<String, String>,
+    /// A Roslyn server checked into the repository, relative to the worktree
+    /// root, e.g. `tools/csharp-language-server`. Used instead of a download;
+    /// `binary.path` still takes precedence. Empty (the default) to always
+    /// download, so that opening a repository never runs its files.
+    pub repo_server_path: String,
+    /// How much to write to `csharp-extension.log` in the extension's working
+    /// directory: `off`, `error`, `warn`, `info` (the default) or `debug`.
+    pub log_level: LogLevel,
+    /// The most disk space, in megabytes, that installs and caches in the
+    /// extension's working directory may use before the least recently
+    /// updated versions are removed. Defaults to 4096.
+    pub cache_size_mb: u64,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, serde_json::Value>,
+}
+
+impl Default for CsharpSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dotnet_path: None,
+            check_workloads: false,
+            required_workloads: Vec::new(),
+            manage_installs: true,
+            http_headers: BTreeMap::new(),
+            excluded_version_patterns: Vec::new(),
+            unity: true,
+            verify_downloads: false,
+            auto_download: AutoDownload::Always,
+            preserve_files: BTreeMap::new(),
+            redirects: Redirects::Follow,
+            server_versions: BTreeMap::new(),
+            repo_server_path: String::new(),
+            log_level: LogLevel::Info,
+            cache_size_mb: 4096,
+            unknown: BTreeMap::new(),
+        }
+    }
+}
+
+const CSHARP_SETTINGS_KEYS: &[&str] = &[
+    "enabled",
+    "dotnet_path",
+    "check_workloads",
+    "required_workloads",
+    "manage_installs",
+    "http_headers",
+    "excluded_version_patterns",
+    "unity",
+    "verify_downloads",
+    "auto_download",
+    "preserve_files",
+    "redirects",
+    "server_versions",
+    "repo_server_path",
+    "log_level",
+    "cache_size_mb",
+];
+
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AutoDownload {
+    #[default]
+    Always,
+    /// Fail instead of downloading, for environments that only allow
+    /// pre-approved binaries.
+    Never,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Redirects {
+    /// Follow redirects, dropping credential headers when the host changes or
+    /// the redirect leaves HTTPS.
+    #[default]
+    Follow,
+    /// Follow redirects and send every header to the new host too, unless
+    /// the redirect leaves HTTPS.
+    FollowWithCredentials,
+    /// Fail instead of following a redirect.
+    Never,
+}
+
+impl CsharpSettings {
+    pub fn for_worktree(worktree: &zed::Worktree) -> Self {
+        Self::load(worktree).0
+    }
+
+    /// The version pinned in `server_versions` for a language server.
+    pub fn pinned_version(&self, language_server_id: &str) -> Option<&str> {
+        self.server_versions
+            .get(language_server_id)
+            .map(String::as_str)
+    }
+
+    /// Returns the settings along with human-readable descriptions of any
+    /// unknown keys or invalid values.
+    pub fn load(worktree: &zed::Worktree) -> (Self, Vec<String>) {
+        match LspSettings::for_worktree(EXTENSION_SETTINGS_KEY, worktree)
+            .ok()
+            .and_then(|lsp_settings| lsp_settings.settings)
+        {
+            Some(value) => Self::from_value(value),
+            None => (Self::default(), Vec::new()),
+        }
+    }
+
+    /// Parses each key on its own, so that one invalid value only resets that
+    /// setting to its default. An invalid `auto_download`, or settings that
+    /// aren't an object at all, never allow downloads the user may have
+    /// turned off.
+    fn from_value(value: serde_json::Value) -> (Self, Vec<String>) {
+        let serde_json::Value::Object(map) = value else {
+            let settings = Self {
+                auto_download: AutoDownload::Never,
+                ..Self::default()
+            };
+            return (
+                settings,
+                vec![format!(
+                    "invalid `{EXTENSION_SETTINGS_KEY}.settings`: expected an object; \
+                     downloads are disabled until this is fixed"
+                )],
+            );
+        };
+
+        let mut problems = Vec::new();
+        let mut valid = serde_json::Map::new();
+        let mut invalid_auto_download = false;
+        for (key, value) in map {
+            let single =
+                serde_json::Value::Object([(key.clone(), value.clone())].into_iter().collect());
+            match serde_json::from_value::<Self>(single) {
+                Ok(_) => {
+                    valid.insert(key, value);
+                }
+                Err(e) => {
+                    let fallback = if key == "auto_download" {
+                        invalid_auto_download = true;
+                        "downloads are disabled until this is fixed"
+                    } else {
+                        "using the default"
+                    };
+                    problems.push(format!(
+                        "invalid `{EXTENSION_SETTINGS_KEY}.settings.{key}`: {e}; {fallback}"
+                    ));
+                }
+            }
+        }
+
+        let mut settings =
+            serde_json::from_value::<Self>(serde_json::Value::Object(valid)).unwrap_or_default();
+        if invalid_auto_download {
+            settings.auto_download = AutoDownload::Never;
+        }
+        problems.extend(
+            settings
+                .unknown
+                .keys()
+                .map(|key| unknown_key_message(EXTENSION_SETTINGS_KEY, key, CSHARP_SETTINGS_KEYS)),
+        );
+        (settings, problems)
+    }
+}
+
+/// The keys in `lsp.omnisharp.settings` that the extension translates rather
+/// than passing straight through to OmniSharp.
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct OmnisharpExtensionSettings {
+    /// MSBuild properties, sent as `MsBuild.MSBuildProperties`.
+    pub msbuild_properties: BTreeMap<String, String>,
+    /// Globs limiting project discovery, sent as `FileOptions.Include`.
+    pub project_roots: Vec<String>,
+    /// Globs OmniSharp should neither watch nor analyze, added to
+    /// `FileOptions.ExcludeSearchPatterns`. Defaults to build output and
+    /// `node_modules`.
+    pub file_exclude_globs: Vec<String>,
+    /// Arguments placed before `-lsp` on the OmniSharp command line. Ignored
+    /// when `binary.arguments` is set, since that replaces all arguments.
+    pub prepend_args: Vec<String>,
+}
+
+impl Default for OmnisharpExtensionSettings {
+    fn default() -> Self {
+        Self {
+            msbuild_properties: BTreeMap::new(),
+            project_roots: Vec::new(),
+            file_exclude_globs: ["**/bin/**", "**/obj/**", "**/node_modules/**"]
+                .map(str::to_string)
+                .to_vec(),
+            prepend_args: Vec::new(),
+        }
+    }
+}
+
+impl OmnisharpExtensionSettings {
+    const KEYS: &[&str] = &[
+        "msbuild_properties",
+        "project_roots",
+        "file_exclude_globs",
+        "prepend_args",
+    ];
+
+    /// Removes the extension-owned keys from `settings` and parses them.
+    pub fn take_from(
+        settings: &mut serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Self, String> {
+        let owned: serde_json::Map<_, _> = Self::KEYS
+            .iter()
+            .filter_map(|key| Some((key.to_string(), settings.remove(*key)?)))
+            .collect();
+        serde_json::from_value(owned.into())
+            .map_err(|e| format!("invalid `omnisharp.settings`: {e}"))
+    }
+}
+
+fn unknown_key_message(section: &str, key: &str, known_keys: &[&str]) -> String {
+    let suggestion = known_keys
+        .iter()
+        .map(|known| (edit_distance(key, known), known))
+        .filter(|(distance, _)| *distance <= 3)
+        .min_by_key(|(distance, _)| *distance);
+    match suggestion {
+        Some((_, known)) => {
+            format!("unknown key `{section}.settings.{key}`, did you mean `{known}`?")
+        }
+        None => format!("unknown key `{section}.settings.{key}`"),
+    }
+}
+
+/// Levenshtein distance, ignoring ASCII case.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_ascii_lowercase().chars().collect();
+    let b: Vec<char> = b.to_ascii_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zed_extension_api::serde_json::json;
+
+    #[test]
+    fn invalid_key_only_resets_that_key() {
+        let (settings, problems) = CsharpSettings::from_value(json!({
+            "enabled": false,
+            "manage_installs": "sometimes",
+            "cache_size_mb": 10,
+        }));
+        assert!(!settings.enabled);
+        assert!(settings.manage_installs);
+        assert_eq!(settings.cache_size_mb, 10);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("manage_installs"));
+    }
+
+    #[test]
+    fn invalid_auto_download_disables_downloads() {
+        let (settings, problems) = CsharpSettings::from_value(json!({ "auto_download": "ask" }));
+        assert!(settings.auto_download == AutoDownload::Never);
+        assert_eq!(problems.len(), 1);
+
+        let (settings, _) = CsharpSettings::from_value(json!(["never"]));
+        assert!(settings.auto_download == AutoDownload::Never);
+    }
+
+    #[test]
+    fn invalid_sibling_keeps_auto_download_never() {
+        let (settings, _) = CsharpSettings::from_value(json!({
+            "auto_download": "never",
+            "log_level": 3,
+        }));
+        assert!(settings.auto_download == AutoDownload::Never);
+        assert!(settings.log_level == LogLevel::Info);
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("Log_Level", "log_level"), 0);
+        assert_eq!(edit_distance("cache_size", "cache_size_mb"), 3);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let (_, problems) = CsharpSettings::from_value(json!({ "dotnet_pth": "/usr/bin/dotnet" }));
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("dotnet_path"), "{}", problems[0]);
+    }
+}
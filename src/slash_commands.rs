@@ -0,0 +1,459 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use zed_extension_api::{
+    self as zed, settings::LspSettings, Result, SlashCommandOutput, SlashCommandOutputSection,
+};
+
+use crate::cache::{self, owned_entries, LANGUAGE_SERVER_IDS};
+use crate::dotnet::DotnetLocator;
+use crate::http;
+use crate::installed::{self, InstalledVersions};
+use crate::language_servers::{
+    nuget::{NuGetClient, PackageSearchResult},
+    util, CsharpLs, Omnisharp, Roslyn, RoslynBinaryInfo,
+};
+use crate::logging;
+use crate::project;
+use crate::settings::CsharpSettings;
+
+const NUGET_SEARCH_RESULTS: u32 = 5;
+
+const VERSIONS_LISTED: usize = 20;
+
+const TYPE_KINDS: [&str; 5] = ["class", "interface", "record", "struct", "enum"];
+
+/// `/nuget <query> [--prerelease]`: lists the best matches on NuGet and renders
+/// a `PackageReference` for the first one.
+pub fn nuget(nuget: &mut NuGetClient, args: Vec<String>) -> Result<SlashCommandOutput> {
+    let include_prerelease = args.iter().any(|arg| arg == "--prerelease");
+    let query = args
+        .iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if query.is_empty() {
+        return Err("usage: /nuget <package name> [--prerelease]".to_string());
+    }
+
+    let results = nuget.fuzzy_search(&query, NUGET_SEARCH_RESULTS, include_prerelease)?;
+    let first = results
+        .first()
+        .ok_or_else(|| format!("no NuGet packages found for '{query}'"))?;
+
+    let mut text = format_search_results(&results);
+    text.push_str(&format!(
+        "\n<PackageReference Include=\"{}\" Version=\"{}\" />\n",
+        first.id, first.latest_version
+    ));
+
+    Ok(SlashCommandOutput {
+        sections: vec![SlashCommandOutputSection {
+            range: (0..text.len()).into(),
+            label: format!("NuGet: {query}"),
+        }],
+        text,
+    })
+}
+
+/// `/csharp-add-package <query> [--version <version>]`: lists the best matches
+/// on NuGet and, when a version is given, the `dotnet add package` command for
+/// the best match.
+pub fn add_package(nuget: &mut NuGetClient, args: Vec<String>) -> Result<SlashCommandOutput> {
+    let mut version = None;
+    let mut query_parts = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--version" {
+            version = args.next();
+        } else {
+            query_parts.push(arg);
+        }
+    }
+    let query = query_parts.join(" ");
+    if query.is_empty() {
+        return Err("usage: /csharp-add-package <package> [--version <version>]".to_string());
+    }
+
+    let results = nuget.fuzzy_search(&query, NUGET_SEARCH_RESULTS, false)?;
+    if results.is_empty() {
+        return Err(format!("no NuGet packages found for '{query}'"));
+    }
+
+    let mut text = format_search_results(&results);
+    if let Some(version) = version {
+        let package = results
+            .iter()
+            .find(|result| result.id.eq_ignore_ascii_case(&query))
+            .unwrap_or(&results[0]);
+        text.push_str(&format!(
+            "\n```sh\ndotnet add package {} --version {version}\n```\n",
+            package.id
+        ));
+    }
+
+    Ok(SlashCommandOutput {
+        sections: vec![SlashCommandOutputSection {
+            range: (0..text.len()).into(),
+            label: format!("NuGet: {query}"),
+        }],
+        text,
+    })
+}
+
+/// `/csharp-new-type <kind> <path/to/Name>`: renders a new type with the
+/// namespace of the containing project and folder. Extensions can't write to
+/// the worktree, so the file itself is left to the user.
+pub fn new_type(worktree: Option<&zed::Worktree>, args: Vec<String>) -> Result<SlashCommandOutput> {
+    let usage = || {
+        format!(
+            "usage: /csharp-new-type <{}> <path/to/Name>",
+            TYPE_KINDS.join("|")
+        )
+    };
+    let [kind, path] = args.as_slice() else {
+        return Err(usage());
+    };
+    if !TYPE_KINDS.contains(&kind.as_str()) {
+        return Err(usage());
+    }
+    let worktree = worktree.ok_or("/csharp-new-type needs an open worktree")?;
+
+    let path = path.trim_end_matches(".cs");
+    let (dir, name) = path.rsplit_once(['/', '\\']).unwrap_or(("", path));
+    let namespace = project::namespace_for(worktree, dir).ok_or_else(|| {
+        format!("no project found for '{dir}'; expected a <Folder>/<Folder>.csproj above it")
+    })?;
+
+    let declaration = format!("public {kind} {name}\n{{\n}}\n");
+    let body = if project::prefers_block_scoped_namespaces(worktree) {
+        let indented: String = declaration
+            .lines()
+            .map(|line| format!("    {line}\n"))
+            .collect();
+        format!("namespace {namespace}\n{{\n{indented}}}\n")
+    } else {
+        format!("namespace {namespace};\n\n{declaration}")
+    };
+    let text = format!("`{path}.cs`\n\n```csharp\n{body}```\n");
+
+    Ok(SlashCommandOutput {
+        sections: vec![SlashCommandOutputSection {
+            range: (0..text.len()).into(),
+            label: format!("New {kind}: {name}"),
+        }],
+        text,
+    })
+}
+
+fn format_search_results(results: &[PackageSearchResult]) -> String {
+    let mut text = String::new();
+    for result in results {
+        text.push_str(&format!("- **{}** `{}`", result.id, result.latest_version));
+        if result.verified {
+            text.push_str(" (verified)");
+        }
+        if !result.description.is_empty() {
+            text.push_str(&format!(": {}", first_line(&result.description)));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// `/csharp-doctor`: a plain-text environment report to paste into bug reports.
+pub fn doctor(
+    worktree: Option<&zed::Worktree>,
+    roslyn: Option<&RoslynBinaryInfo>,
+    recorded: &InstalledVersions,
+) -> Result<SlashCommandOutput> {
+    let mut report = String::new();
+    let (os, arch) = zed::current_platform();
+    writeln!(report, "zed_csharp: {}", env!("CARGO_PKG_VERSION")).ok();
+    writeln!(report, "platform: {os:?} {arch:?}").ok();
+    writeln!(report, "log: {}", logging::log_path()).ok();
+
+    writeln!(report, "\ninstalled language servers:").ok();
+    let installed = owned_entries();
+    if installed.is_empty() {
+        writeln!(report, "  (none)").ok();
+    }
+    for name in installed {
+        writeln!(report, "  {name}").ok();
+    }
+
+    let mut recorded = recorded.iter().peekable();
+    if recorded.peek().is_some() {
+        writeln!(report, "\nrecorded versions:").ok();
+    }
+    for (component, component_install) in recorded {
+        writeln!(
+            report,
+            "  {component}: {} (installed {})",
+            component_install.version,
+            installed::format_date(component_install.installed_at)
+        )
+        .ok();
+    }
+
+    if let Some(roslyn) = roslyn {
+        writeln!(
+            report,
+            "\nrunning {}: {} {} [{}]",
+            Roslyn::LANGUAGE_SERVER_ID,
+            roslyn.package_id,
+            roslyn.version,
+            roslyn.path()
+        )
+        .ok();
+        writeln!(report, "  {}", roslyn.source.description()).ok();
+        if let Some(installed_at) = roslyn.installed_at {
+            writeln!(
+                report,
+                "  installed {}",
+                installed::format_date(installed_at)
+            )
+            .ok();
+        }
+    }
+
+    if let Some(worktree) = worktree {
+        writeln!(report, "\nconfigured binaries:").ok();
+        for id in LANGUAGE_SERVER_IDS {
+            let path = LspSettings::for_worktree(id, worktree)
+                .ok()
+                .and_then(|lsp_settings| lsp_settings.binary)
+                .and_then(|binary| binary.path);
+            writeln!(report, "  {id}: {}", path.as_deref().unwrap_or("(default)")).ok();
+        }
+
+        let (settings, problems) = CsharpSettings::load(worktree);
+        if !problems.is_empty() {
+            writeln!(report, "\nsettings problems:").ok();
+            for problem in problems {
+                writeln!(report, "  {problem}").ok();
+            }
+        }
+
+        let headers: Vec<_> = settings.http_headers.into_iter().collect();
+        let headers = http::redact_headers(&headers);
+        if !headers.is_empty() {
+            writeln!(report, "\nextra HTTP headers:").ok();
+            for (name, value) in headers {
+                writeln!(report, "  {name}: {value}").ok();
+            }
+        }
+
+        match DotnetLocator::default().install(worktree) {
+            Some(install) => {
+                writeln!(report, "\ndotnet: {}", install.executable).ok();
+                writeln!(
+                    report,
+                    "DOTNET_ROOT: {}",
+                    install.root.as_deref().unwrap_or("(unset)")
+                )
+                .ok();
+                writeln!(report, "\nSDKs:").ok();
+                for sdk in &install.sdks {
+                    writeln!(report, "  {} [{}]", sdk.version, sdk.path).ok();
+                }
+                writeln!(report, "\nruntimes:").ok();
+                for runtime in &install.runtimes {
+                    writeln!(
+                        report,
+                        "  {} {} [{}]",
+                        runtime.name, runtime.version, runtime.path
+                    )
+                    .ok();
+                }
+            }
+            None => {
+                writeln!(report, "\ndotnet: (not found)").ok();
+            }
+        }
+    }
+
+    let text = redact_credentials(&report);
+    Ok(SlashCommandOutput {
+        sections: vec![SlashCommandOutputSection {
+            range: (0..text.len()).into(),
+            label: "C# environment".to_string(),
+        }],
+        text,
+    })
+}
+
+/// `/csharp-reinstall`: deletes every install the extension owns so that each
+/// server is downloaded again the next time it starts.
+///
+/// The cached GitHub release metadata and NuGet service index are removed
+/// too, so the latest release and the feed's endpoints are looked up again.
+/// Nothing is removed while an install holds its lock, since that would
+/// delete the files it is extracting. The caller resets the servers' cached
+/// state.
+pub fn reinstall() -> Result<SlashCommandOutput> {
+    if util::install_in_progress() {
+        return Err(
+            "a language server is being installed; run /csharp-reinstall again once it has \
+             started"
+                .to_string(),
+        );
+    }
+
+    let mut removed = Vec::new();
+    let mut failed = Vec::new();
+    for name in owned_entries() {
+        let removal = if Path::new(&name).is_dir() {
+            fs::remove_dir_all(&name)
+        } else {
+            fs::remove_file(&name)
+        };
+        match removal {
+            Ok(()) => removed.push(name),
+            Err(e) => failed.push(format!("{name}: {e}")),
+        }
+    }
+
+    let mut text = String::new();
+    if removed.is_empty() && failed.is_empty() {
+        text.push_str("Nothing to remove.\n");
+    }
+    if !removed.is_empty() {
+        text.push_str("Removed:\n");
+        for name in &removed {
+            writeln!(text, "  {name}").ok();
+        }
+        text.push_str("\nRestart the language server to reinstall it.\n");
+    }
+    if !failed.is_empty() {
+        text.push_str("\nFailed to remove:\n");
+        for failure in &failed {
+            writeln!(text, "  {failure}").ok();
+        }
+    }
+
+    Ok(SlashCommandOutput {
+        sections: vec![SlashCommandOutputSection {
+            range: (0..text.len()).into(),
+            label: "C# reinstall".to_string(),
+        }],
+        text,
+    })
+}
+
+/// `/csharp-versions <roslyn|csharp-ls>`: lists the newest published versions
+/// of a language server and how to pin one.
+pub fn versions(nuget: &mut NuGetClient, args: Vec<String>) -> Result<SlashCommandOutput> {
+    let server = args.first().map(String::as_str).unwrap_or_default();
+    let versions = match server {
+        Roslyn::LANGUAGE_SERVER_ID => Roslyn::available_versions(nuget)?,
+        CsharpLs::LANGUAGE_SERVER_ID => CsharpLs::available_versions(nuget)?,
+        Omnisharp::LANGUAGE_SERVER_ID => {
+            return Err(
+                "OmniSharp versions are GitHub releases, which extensions can only \
+                 query for the latest; see https://github.com/OmniSharp/omnisharp-roslyn/releases"
+                    .to_string(),
+            )
+        }
+        _ => return Err("usage: /csharp-versions <roslyn|csharp-ls>".to_string()),
+    };
+    let newest = versions
+        .first()
+        .ok_or_else(|| format!("no published versions found for {server}"))?;
+
+    let mut text = String::new();
+    for version in versions.iter().take(VERSIONS_LISTED) {
+        writeln!(text, "{version}").ok();
+    }
+    if versions.len() > VERSIONS_LISTED {
+        writeln!(
+            text,
+            "({} older versions)",
+            versions.len() - VERSIONS_LISTED
+        )
+        .ok();
+    }
+    writeln!(
+        text,
+        "\nTo pin one, set `lsp.csharp.settings.server_versions`, e.g.\n\
+         \"server_versions\": {{ \"{server}\": \"{newest}\" }}"
+    )
+    .ok();
+
+    Ok(SlashCommandOutput {
+        sections: vec![SlashCommandOutputSection {
+            range: (0..text.len()).into(),
+            label: format!("{server} versions"),
+        }],
+        text,
+    })
+}
+
+/// `/csharp-clean-caches`: removes every install that isn't in use, pinned or
+/// the recorded version, and shows the disk usage per component before and
+/// after.
+pub fn clean_caches(
+    keep: &[String],
+    preserve_files: &BTreeMap<String, Vec<String>>,
+) -> Result<SlashCommandOutput> {
+    let before = cache::disk_usage();
+    let removed = cache::collect_garbage(0, keep, preserve_files);
+    let after = cache::disk_usage();
+
+    let mut text = String::new();
+    writeln!(text, "{:<40} {:>10} {:>10}", "component", "before", "after").ok();
+    for (component, size) in &before {
+        writeln!(
+            text,
+            "{component:<40} {:>10} {:>10}",
+            cache::format_size(*size),
+            cache::format_size(after.get(component).copied().unwrap_or_default())
+        )
+        .ok();
+    }
+    let total = |usage: &BTreeMap<String, u64>| usage.values().sum::<u64>();
+    writeln!(
+        text,
+        "{:<40} {:>10} {:>10}",
+        "total",
+        cache::format_size(total(&before)),
+        cache::format_size(total(&after))
+    )
+    .ok();
+
+    if removed.is_empty() {
+        text.push_str("\nNothing to remove.\n");
+    } else {
+        text.push_str("\nRemoved:\n");
+        for (entry, size) in &removed {
+            writeln!(text, "  {entry} ({})", cache::format_size(*size)).ok();
+        }
+    }
+
+    Ok(SlashCommandOutput {
+        sections: vec![SlashCommandOutputSection {
+            range: (0..text.len()).into(),
+            label: "C# caches".to_string(),
+        }],
+        text,
+    })
+}
+
+/// Strips `user:password@` from any URLs in the report.
+fn redact_credentials(text: &str) -> String {
+    text.split_inclusive(char::is_whitespace)
+        .map(|word| match (word.find("://"), word.find('@')) {
+            (Some(scheme_end), Some(at)) if scheme_end < at => {
+                format!("{}://<redacted>{}", &word[..scheme_end], &word[at..])
+            }
+            _ => word.to_string(),
+        })
+        .collect()
+}
+
+fn first_line(text: &str) -> &str {
+    text.lines().next().unwrap_or_default().trim()
+}